@@ -1,11 +1,11 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use mcp_sdk::resources::Resources;
 use mcp_sdk::server::Server;
 use mcp_sdk::transport::ServerStdioTransport;
 use mcp_sdk::types::{
-    CallToolRequest, CallToolResponse, ListRequest, ResourcesListResponse, ServerCapabilities,
-    ToolResponseContent, ToolsListResponse,
+    CallToolRequest, CallToolResponse, ListRequest, ToolResponseContent, ToolsListResponse,
 };
 use serde_json::json;
 
@@ -17,20 +17,11 @@ async fn main() -> Result<()> {
         .with_writer(std::io::stderr)
         .init();
 
-    let server = Server::builder(ServerStdioTransport)
-        .capabilities(ServerCapabilities {
-            tools: Some(json!({})),
-            ..Default::default()
-        })
+    // capabilities are derived from the registered handlers
+    let server = Server::builder(ServerStdioTransport::default())
         .request_handler("tools/list", list_tools)
         .request_handler("tools/call", call_tool)
-        .request_handler("resources/list", |_req: ListRequest| {
-            Ok(ResourcesListResponse {
-                resources: vec![],
-                next_cursor: None,
-                meta: None,
-            })
-        })
+        .resources(Resources::default())
         .build();
     let server_handle = {
         let server = server;
@@ -50,7 +41,7 @@ fn call_tool(req: CallToolRequest) -> Result<CallToolResponse> {
         "read_file" => {
             let path = get_path(&args)?;
             let content = std::fs::read_to_string(path)?;
-            ToolResponseContent::Text { text: content }
+            ToolResponseContent::text(content)
         }
         "list_directory" => {
             let path = get_path(&args)?;
@@ -68,32 +59,27 @@ fn call_tool(req: CallToolRequest) -> Result<CallToolResponse> {
                     entry.file_name().to_string_lossy()
                 ));
             }
-            ToolResponseContent::Text { text }
+            ToolResponseContent::text(text)
         }
         "search_files" => {
             let path = get_path(&args)?;
             let pattern = args["pattern"].as_str().unwrap();
             let mut matches = Vec::new();
             search_directory(&path, pattern, &mut matches)?;
-            ToolResponseContent::Text {
-                text: matches.join("\n"),
-            }
+            ToolResponseContent::text(matches.join("\n"))
         }
         "get_file_info" => {
             let path = get_path(&args)?;
             let metadata = std::fs::metadata(path)?;
-            ToolResponseContent::Text {
-                text: format!("{:?}", metadata),
-            }
+            ToolResponseContent::text(format!("{:?}", metadata))
         }
-        "list_allowed_directories" => ToolResponseContent::Text {
-            text: "[]".to_string(),
-        },
+        "list_allowed_directories" => ToolResponseContent::text("[]"),
         _ => return Err(anyhow::anyhow!("Unknown tool: {}", req.name)),
     };
     Ok(CallToolResponse {
         content: vec![result],
         is_error: None,
+        structured_content: None,
         meta: None,
     })
 }
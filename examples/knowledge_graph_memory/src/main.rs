@@ -1,7 +1,6 @@
 use std::sync::{Arc, Mutex};
 
-use mcp_sdk::{server::Server, transport::ServerStdioTransport, types::ServerCapabilities};
-use serde_json::json;
+use mcp_sdk::{server::Server, transport::ServerStdioTransport};
 use types::KnowledgeGraph;
 
 use anyhow::Result;
@@ -17,13 +16,9 @@ async fn main() -> Result<()> {
     let memory_file_path = "kb_memory.json";
     let kg = KnowledgeGraph::load_from_file(memory_file_path)?;
     let kg = Arc::new(Mutex::new(kg));
-    let tools = tool_set::tool_set(kg, memory_file_path.to_string());
+    let tools = tool_set::tool_set(kg, memory_file_path.to_string())?;
 
-    let server = Server::builder(ServerStdioTransport)
-        .capabilities(ServerCapabilities {
-            tools: Some(json!({})),
-            ..Default::default()
-        })
+    let server = Server::builder(ServerStdioTransport::default())
         .tools(tools)
         .build();
 
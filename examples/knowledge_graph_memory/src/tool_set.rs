@@ -3,42 +3,49 @@ use crate::types::{
 };
 use anyhow::Result;
 use mcp_sdk::{
-    tools::{Tool, Tools},
+    tools::{Structured, Tools, TypedTool},
     types::{CallToolResponse, ToolResponseContent},
 };
+use schemars::JsonSchema;
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::{Arc, Mutex};
 
-pub fn tool_set(kg: Arc<Mutex<KnowledgeGraph>>, memory_file_path: String) -> Tools {
+pub fn tool_set(kg: Arc<Mutex<KnowledgeGraph>>, memory_file_path: String) -> Result<Tools> {
     let mut tools = Tools::default();
-    tools.add_tool(CreateEntitiesTool::new(
+    tools.add_typed_tool(CreateEntitiesTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(CreateRelationsTool::new(
+    ))?;
+    tools.add_typed_tool(CreateRelationsTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(ReadGraphTool::new(kg.clone()));
-    tools.add_tool(AddObservationsTool::new(
+    ))?;
+    tools.add_typed_tool(ReadGraphTool::new(kg.clone()))?;
+    tools.add_typed_tool(AddObservationsTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(DeleteEntitiesTool::new(
+    ))?;
+    tools.add_typed_tool(DeleteEntitiesTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(DeleteObservationsTool::new(
+    ))?;
+    tools.add_typed_tool(DeleteObservationsTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(DeleteRelationsTool::new(
+    ))?;
+    tools.add_typed_tool(DeleteRelationsTool::new(
         kg.clone(),
         memory_file_path.clone(),
-    ));
-    tools.add_tool(SearchNodesTool::new(kg.clone()));
-    tools.add_tool(OpenNodesTool::new(kg.clone()));
-    tools
+    ))?;
+    tools.add_typed_tool(SearchNodesTool::new(kg.clone()))?;
+    tools.add_typed_tool(OpenNodesTool::new(kg.clone()))?;
+    Ok(tools)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateEntitiesArgs {
+    pub entities: Vec<Entity>,
 }
 
 pub struct CreateEntitiesTool {
@@ -55,7 +62,10 @@ impl CreateEntitiesTool {
     }
 }
 
-impl Tool for CreateEntitiesTool {
+impl TypedTool for CreateEntitiesTool {
+    type Args = CreateEntitiesArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "create_entities".to_string()
     }
@@ -64,51 +74,27 @@ impl Tool for CreateEntitiesTool {
         "Create multiple new entities".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-           "type":"object",
-           "properties":{
-              "entities":{
-                 "type":"array",
-                 "items":{
-                    "type":"object",
-                    "properties":{
-                       "name":{"type":"string"},
-                       "entityType":{"type":"string"},
-                       "observations":{
-                          "type":"array", "items":{"type":"string"}
-                       }
-                    },
-                    "required":["name","entityType","observations"]
-                 }
-              }
-           },
-           "required":["entities"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let entities = args
-            .get("entities")
-            .ok_or(anyhow::anyhow!("missing arguments `entities`"))?;
-        let entities: Vec<Entity> = serde_json::from_value(entities.clone())?;
-        let created = self.kg.lock().unwrap().create_entities(entities)?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
+        let created = self.kg.lock().unwrap().create_entities(args.entities)?;
         self.kg
             .lock()
             .unwrap()
             .save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(created).to_string(),
-            }],
+            content: vec![ToolResponseContent::text(json!(created).to_string())],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
 }
 
 // Similar pattern for other tools...
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateRelationsArgs {
+    pub relations: Vec<Relation>,
+}
+
 pub struct CreateRelationsTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
     memory_file_path: String,
@@ -123,7 +109,10 @@ impl CreateRelationsTool {
     }
 }
 
-impl Tool for CreateRelationsTool {
+impl TypedTool for CreateRelationsTool {
+    type Args = CreateRelationsArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "create_relations".to_string()
     }
@@ -132,43 +121,16 @@ impl Tool for CreateRelationsTool {
         "Create multiple new relations".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-           "type":"object",
-           "properties":{
-              "relations":{
-                 "type":"array",
-                 "items":{
-                    "type":"object",
-                    "properties":{
-                       "from":{"type":"string"},
-                       "to":{"type":"string"},
-                       "relationType":{"type":"string"}
-                    },
-                    "required":["from","to","relationType"]
-                 }
-              }
-           },
-           "required":["relations"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let relations = args
-            .get("relations")
-            .ok_or(anyhow::anyhow!("missing arguments `relations`"))?;
-        let relations: Vec<Relation> = serde_json::from_value(relations.clone())?;
-        let created = self.kg.lock().unwrap().create_relations(relations)?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
+        let created = self.kg.lock().unwrap().create_relations(args.relations)?;
         self.kg
             .lock()
             .unwrap()
             .save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(created).to_string(),
-            }],
+            content: vec![ToolResponseContent::text(json!(created).to_string())],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
@@ -177,6 +139,9 @@ impl Tool for CreateRelationsTool {
 // Continue implementing for each tool (AddObservationsTool, DeleteEntitiesTool, etc.)
 // I'll show one more as example:
 
+#[derive(Deserialize, JsonSchema)]
+pub struct ReadGraphArgs {}
+
 pub struct ReadGraphTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
 }
@@ -187,7 +152,10 @@ impl ReadGraphTool {
     }
 }
 
-impl Tool for ReadGraphTool {
+impl TypedTool for ReadGraphTool {
+    type Args = ReadGraphArgs;
+    type Output = Structured<KnowledgeGraph>;
+
     fn name(&self) -> String {
         "read_graph".to_string()
     }
@@ -196,22 +164,14 @@ impl Tool for ReadGraphTool {
         "Read the entire knowledge graph".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {}
-        })
+    fn call(&self, _args: Self::Args) -> Result<Self::Output> {
+        Ok(Structured(self.kg.lock().unwrap().clone()))
     }
+}
 
-    fn call(&self, _input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(*self.kg.lock().unwrap()).to_string(),
-            }],
-            is_error: None,
-            meta: None,
-        })
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct AddObservationsArgs {
+    pub observations: Vec<AddObservationParams>,
 }
 
 pub struct AddObservationsTool {
@@ -228,7 +188,10 @@ impl AddObservationsTool {
     }
 }
 
-impl Tool for AddObservationsTool {
+impl TypedTool for AddObservationsTool {
+    type Args = AddObservationsArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "add_observations".to_string()
     }
@@ -237,50 +200,31 @@ impl Tool for AddObservationsTool {
         "Add new observations to existing entities".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "observations": {
-                    "type": "array",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "entityName": {"type": "string"},
-                            "contents": {
-                                "type": "array",
-                                "items": {"type": "string"}
-                            }
-                        },
-                        "required": ["entityName", "contents"]
-                    }
-                }
-            },
-            "required": ["observations"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let observations = args
-            .get("observations")
-            .ok_or(anyhow::anyhow!("missing arguments `observations`"))?;
-        let observations: Vec<AddObservationParams> = serde_json::from_value(observations.clone())?;
-        let results = self.kg.lock().unwrap().add_observations(observations)?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
+        let results = self
+            .kg
+            .lock()
+            .unwrap()
+            .add_observations(args.observations)?;
         self.kg
             .lock()
             .unwrap()
             .save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(results).to_string(),
-            }],
+            content: vec![ToolResponseContent::text(json!(results).to_string())],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteEntitiesArgs {
+    #[serde(rename = "entityNames")]
+    pub entity_names: Vec<String>,
+}
+
 pub struct DeleteEntitiesTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
     memory_file_path: String,
@@ -295,7 +239,10 @@ impl DeleteEntitiesTool {
     }
 }
 
-impl Tool for DeleteEntitiesTool {
+impl TypedTool for DeleteEntitiesTool {
+    type Args = DeleteEntitiesArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "delete_entities".to_string()
     }
@@ -304,38 +251,24 @@ impl Tool for DeleteEntitiesTool {
         "Delete multiple entities and their relations".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "entityNames": {
-                    "type": "array",
-                    "items": {"type": "string"}
-                }
-            },
-            "required": ["entityNames"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let entity_names = args
-            .get("entityNames")
-            .ok_or(anyhow::anyhow!("missing arguments `entityNames`"))?;
-        let entity_names: Vec<String> = serde_json::from_value(entity_names.clone())?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
         let mut kg_guard = self.kg.lock().unwrap();
-        kg_guard.delete_entities(entity_names)?;
+        kg_guard.delete_entities(args.entity_names)?;
         kg_guard.save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: "Entities deleted successfully".to_string(),
-            }],
+            content: vec![ToolResponseContent::text("Entities deleted successfully")],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteObservationsArgs {
+    pub deletions: Vec<DeleteObservationParams>,
+}
+
 pub struct DeleteObservationsTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
     memory_file_path: String,
@@ -350,7 +283,10 @@ impl DeleteObservationsTool {
     }
 }
 
-impl Tool for DeleteObservationsTool {
+impl TypedTool for DeleteObservationsTool {
+    type Args = DeleteObservationsArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "delete_observations".to_string()
     }
@@ -359,48 +295,26 @@ impl Tool for DeleteObservationsTool {
         "Delete specific observations from entities".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "deletions": {
-                    "type": "array",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "entityName": {"type": "string"},
-                            "observations": {
-                                "type": "array",
-                                "items": {"type": "string"}
-                            }
-                        },
-                        "required": ["entityName", "observations"]
-                    }
-                }
-            },
-            "required": ["deletions"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let deletions = args
-            .get("deletions")
-            .ok_or(anyhow::anyhow!("missing arguments `deletions`"))?;
-        let deletions: Vec<DeleteObservationParams> = serde_json::from_value(deletions.clone())?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
         let mut kg_guard = self.kg.lock().unwrap();
-        kg_guard.delete_observations(deletions)?;
+        kg_guard.delete_observations(args.deletions)?;
         kg_guard.save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: "Observations deleted successfully".to_string(),
-            }],
+            content: vec![ToolResponseContent::text(
+                "Observations deleted successfully",
+            )],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteRelationsArgs {
+    pub relations: Vec<Relation>,
+}
+
 pub struct DeleteRelationsTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
     memory_file_path: String,
@@ -415,7 +329,10 @@ impl DeleteRelationsTool {
     }
 }
 
-impl Tool for DeleteRelationsTool {
+impl TypedTool for DeleteRelationsTool {
+    type Args = DeleteRelationsArgs;
+    type Output = CallToolResponse;
+
     fn name(&self) -> String {
         "delete_relations".to_string()
     }
@@ -424,46 +341,24 @@ impl Tool for DeleteRelationsTool {
         "Delete multiple relations from the graph".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "relations": {
-                    "type": "array",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "from": {"type": "string"},
-                            "to": {"type": "string"},
-                            "relationType": {"type": "string"}
-                        },
-                        "required": ["from", "to", "relationType"]
-                    }
-                }
-            },
-            "required": ["relations"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let relations = args
-            .get("relations")
-            .ok_or(anyhow::anyhow!("missing arguments `relations`"))?;
-        let relations: Vec<Relation> = serde_json::from_value(relations.clone())?;
+    fn call(&self, args: Self::Args) -> Result<CallToolResponse> {
         let mut kg_guard = self.kg.lock().unwrap();
-        kg_guard.delete_relations(relations)?;
+        kg_guard.delete_relations(args.relations)?;
         kg_guard.save_to_file(&self.memory_file_path)?;
         Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: "Relations deleted successfully".to_string(),
-            }],
+            content: vec![ToolResponseContent::text("Relations deleted successfully")],
             is_error: None,
+            structured_content: None,
             meta: None,
         })
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct SearchNodesArgs {
+    pub query: String,
+}
+
 pub struct SearchNodesTool {
     kg: Arc<Mutex<KnowledgeGraph>>,
 }
@@ -474,7 +369,10 @@ impl SearchNodesTool {
     }
 }
 
-impl Tool for SearchNodesTool {
+impl TypedTool for SearchNodesTool {
+    type Args = SearchNodesArgs;
+    type Output = Structured<KnowledgeGraph>;
+
     fn name(&self) -> String {
         "search_nodes".to_string()
     }
@@ -483,32 +381,16 @@ impl Tool for SearchNodesTool {
         "Search for nodes in the knowledge graph".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "query": {"type": "string"}
-            },
-            "required": ["query"]
-        })
+    fn call(&self, args: Self::Args) -> Result<Self::Output> {
+        Ok(Structured(
+            self.kg.lock().unwrap().search_nodes(&args.query)?,
+        ))
     }
+}
 
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let query = args
-            .get("query")
-            .ok_or(anyhow::anyhow!("missing argument `query`"))?
-            .as_str()
-            .ok_or(anyhow::anyhow!("query must be a string"))?;
-        let results = self.kg.lock().unwrap().search_nodes(query)?;
-        Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(results).to_string(),
-            }],
-            is_error: None,
-            meta: None,
-        })
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct OpenNodesArgs {
+    pub names: Vec<String>,
 }
 
 pub struct OpenNodesTool {
@@ -521,7 +403,10 @@ impl OpenNodesTool {
     }
 }
 
-impl Tool for OpenNodesTool {
+impl TypedTool for OpenNodesTool {
+    type Args = OpenNodesArgs;
+    type Output = Structured<KnowledgeGraph>;
+
     fn name(&self) -> String {
         "open_nodes".to_string()
     }
@@ -530,32 +415,7 @@ impl Tool for OpenNodesTool {
         "Open specific nodes by their names".to_string()
     }
 
-    fn input_schema(&self) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "names": {
-                    "type": "array",
-                    "items": {"type": "string"}
-                }
-            },
-            "required": ["names"]
-        })
-    }
-
-    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
-        let args = input.unwrap_or_default();
-        let names = args
-            .get("names")
-            .ok_or(anyhow::anyhow!("missing arguments `names`"))?;
-        let names: Vec<String> = serde_json::from_value(names.clone())?;
-        let results = self.kg.lock().unwrap().open_nodes(names)?;
-        Ok(CallToolResponse {
-            content: vec![ToolResponseContent::Text {
-                text: json!(results).to_string(),
-            }],
-            is_error: None,
-            meta: None,
-        })
+    fn call(&self, args: Self::Args) -> Result<Self::Output> {
+        Ok(Structured(self.kg.lock().unwrap().open_nodes(args.names)?))
     }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     fs::File,
@@ -10,7 +11,7 @@ use std::{
 // Data Structures
 // -----------------------------------------------------------------------------
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Entity {
     pub name: String,
     #[serde(rename = "entityType")]
@@ -18,7 +19,7 @@ pub struct Entity {
     pub observations: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Relation {
     pub from: String,
     pub to: String,
@@ -26,7 +27,7 @@ pub struct Relation {
     pub relation_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct KnowledgeGraph {
     pub entities: Vec<Entity>,
     pub relations: Vec<Relation>,
@@ -238,7 +239,7 @@ impl KnowledgeGraph {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct AddObservationParams {
     #[serde(rename = "entityName")]
     pub entity_name: String,
@@ -254,7 +255,7 @@ pub struct AddedObservationResult {
 }
 
 // For delete_observations
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct DeleteObservationParams {
     #[serde(rename = "entityName")]
     pub entity_name: String,
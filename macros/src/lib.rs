@@ -0,0 +1,309 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Attribute, Error, Expr, ExprLit, FnArg, GenericArgument, Ident, ItemFn, Lit,
+    LitBool, LitStr, Meta, Pat, PathArguments, Result, ReturnType, Token, Type,
+};
+
+/// Turn a function into a tool
+///
+/// ```ignore
+/// /// Add two numbers
+/// #[tool(name = "add")]
+/// async fn add(
+///     /// The first number
+///     a: i64,
+///     b: i64,
+/// ) -> anyhow::Result<Structured<Sum>> {
+///     ...
+/// }
+///
+/// AddTool::register(&mut tools)?;
+/// ```
+///
+/// The function is kept as is and a unit struct named after it (`add` -> `AddTool`)
+/// implements `AsyncTool`, sync and async functions are both supported.
+/// `AddTool::register(&mut tools)` adds it to a `Tools` registry like `Tools::add_async_tool`.
+/// The input schema is generated from the parameters, their doc comments become
+/// the property descriptions and `Option` parameters are not required.
+/// A parameter of type `ToolContext` or `&ToolContext` gets the context of the call
+/// instead of an argument.
+/// The function returns a `Result` of any `ToolOutput`, `Structured<T>` results also get
+/// an output schema generated from `T`.
+/// `name` defaults to the function name and `description` to its doc comment.
+/// `title` and the hints `read_only`, `destructive`, `idempotent` and `open_world`
+/// (flags, or `= false`) fill in the tool annotations.
+#[proc_macro_attribute]
+pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = ToolArgs::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            args.name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("description") {
+            args.description = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("title") {
+            args.title = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if let Some(hint) = HINTS.iter().find(|hint| meta.path.is_ident(hint)) {
+            let value = if meta.input.peek(Token![=]) {
+                meta.value()?.parse::<LitBool>()?.value
+            } else {
+                true
+            };
+            args.hints.push((format_ident!("{}_hint", hint), value));
+            Ok(())
+        } else {
+            Err(meta.error(
+                "expected `name`, `description`, `title`, `read_only`, `destructive`, `idempotent` or `open_world`",
+            ))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+const HINTS: [&str; 4] = ["read_only", "destructive", "idempotent", "open_world"];
+
+#[derive(Default)]
+struct ToolArgs {
+    name: Option<LitStr>,
+    description: Option<LitStr>,
+    title: Option<LitStr>,
+    /// `ToolAnnotations` field and value
+    hints: Vec<(Ident, bool)>,
+}
+
+struct Param {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    ty: Box<syn::Type>,
+}
+
+fn expand(args: ToolArgs, mut function: ItemFn) -> Result<proc_macro2::TokenStream> {
+    let sig = &function.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "tool functions cannot be generic",
+        ));
+    }
+
+    let mut params = Vec::new();
+    // what the function is called with, arguments or the context
+    let mut call_args = Vec::new();
+    for input in function.sig.inputs.iter_mut() {
+        let FnArg::Typed(input) = input else {
+            return Err(Error::new_spanned(
+                input,
+                "tool functions cannot take `self`",
+            ));
+        };
+        match context_type(&input.ty) {
+            Some(true) => {
+                call_args.push(quote!(context));
+                continue;
+            }
+            Some(false) => {
+                call_args.push(quote!(::std::clone::Clone::clone(context)));
+                continue;
+            }
+            None => {}
+        }
+        let Pat::Ident(pat) = input.pat.as_ref() else {
+            return Err(Error::new_spanned(
+                &input.pat,
+                "tool parameters must be plain identifiers",
+            ));
+        };
+        let ident = &pat.ident;
+        call_args.push(quote!(args.#ident));
+        // doc comments are not allowed on parameters, move them to the args struct
+        let (docs, attrs) = input
+            .attrs
+            .drain(..)
+            .partition(|attr| attr.path().is_ident("doc"));
+        input.attrs = attrs;
+        params.push(Param {
+            attrs: docs,
+            ident: pat.ident.clone(),
+            ty: input.ty.clone(),
+        });
+    }
+
+    let sig = &function.sig;
+    let output = result_type(&sig.output)?;
+    let fn_ident = &sig.ident;
+    let vis = &function.vis;
+    let tool_ident = format_ident!("{}Tool", pascal_case(&fn_ident.to_string()));
+    let name = args
+        .name
+        .unwrap_or_else(|| LitStr::new(&fn_ident.to_string(), fn_ident.span()));
+    let description = match args.description {
+        Some(description) => description,
+        None => LitStr::new(&doc_comment(&function.attrs), Span::call_site()),
+    };
+    let await_call = sig.asyncness.map(|_| quote!(.await));
+    let title = match &args.title {
+        Some(title) => quote!(::std::option::Option::Some(
+            ::std::string::ToString::to_string(#title)
+        )),
+        None => quote!(::std::option::Option::None),
+    };
+    // the title is part of the definition itself, annotations only carry the hints
+    let annotations = if args.hints.is_empty() {
+        quote!(::std::option::Option::None)
+    } else {
+        let (hints, values): (Vec<_>, Vec<_>) = args.hints.iter().cloned().unzip();
+        quote!(::std::option::Option::Some(::mcp_sdk::types::ToolAnnotations {
+            #(#hints: ::std::option::Option::Some(#values),)*
+            ..::std::default::Default::default()
+        }))
+    };
+
+    let field_attrs = params.iter().map(|param| &param.attrs);
+    let field_idents = params.iter().map(|param| &param.ident);
+    let field_types = params.iter().map(|param| &param.ty);
+    let tool_doc = format!("Tool generated from [`{fn_ident}`]");
+
+    Ok(quote! {
+        #function
+
+        #[doc = #tool_doc]
+        #vis struct #tool_ident;
+
+        impl #tool_ident {
+            /// Add the tool to a registry, failing if the name is taken
+            #vis fn register(
+                tools: &mut ::mcp_sdk::tools::Tools,
+            ) -> ::mcp_sdk::__private::anyhow::Result<bool> {
+                tools.add_async_tool(#tool_ident)
+            }
+        }
+
+        const _: () = {
+            #[derive(
+                ::mcp_sdk::__private::serde::Deserialize,
+                ::mcp_sdk::__private::schemars::JsonSchema
+            )]
+            #[serde(crate = "::mcp_sdk::__private::serde")]
+            #[schemars(crate = "::mcp_sdk::__private::schemars")]
+            struct __McpToolArgs {
+                #(#(#field_attrs)* #field_idents: #field_types,)*
+            }
+
+            #[::mcp_sdk::__private::async_trait::async_trait]
+            impl ::mcp_sdk::tools::AsyncTool for #tool_ident {
+                fn name(&self) -> ::std::string::String {
+                    ::std::string::ToString::to_string(#name)
+                }
+
+                fn title(&self) -> ::std::option::Option<::std::string::String> {
+                    #title
+                }
+
+                fn description(&self) -> ::std::string::String {
+                    ::std::string::ToString::to_string(#description)
+                }
+
+                fn annotations(
+                    &self,
+                ) -> ::std::option::Option<::mcp_sdk::types::ToolAnnotations> {
+                    #annotations
+                }
+
+                fn input_schema(&self) -> ::mcp_sdk::__private::serde_json::Value {
+                    ::mcp_sdk::tools::input_schema::<__McpToolArgs>()
+                }
+
+                fn output_schema(
+                    &self,
+                ) -> ::std::option::Option<::mcp_sdk::__private::serde_json::Value> {
+                    <#output as ::mcp_sdk::tools::ToolOutput>::output_schema()
+                }
+
+                async fn call(
+                    &self,
+                    input: ::std::option::Option<::mcp_sdk::__private::serde_json::Value>,
+                    context: &::mcp_sdk::tools::ToolContext,
+                ) -> ::mcp_sdk::__private::anyhow::Result<::mcp_sdk::types::CallToolResponse> {
+                    let args: __McpToolArgs = ::mcp_sdk::tools::parse_args(input)?;
+                    let output = #fn_ident(#(#call_args),*)#await_call?;
+                    ::mcp_sdk::tools::ToolOutput::into_response(output)
+                }
+            }
+        };
+    })
+}
+
+/// `T` in a `Result<T>` or `Result<T, E>` return type
+fn result_type(output: &ReturnType) -> Result<&Type> {
+    let error = || Error::new_spanned(output, "tool functions must return a `Result`");
+    let ReturnType::Type(_, ty) = output else {
+        return Err(error());
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return Err(error());
+    };
+    let segment = path.path.segments.last().ok_or_else(error)?;
+    if segment.ident != "Result" {
+        return Err(error());
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return Err(error());
+    };
+    match arguments.args.first() {
+        Some(GenericArgument::Type(ty)) => Ok(ty),
+        _ => Err(error()),
+    }
+}
+
+/// `Some(true)` for `&ToolContext`, `Some(false)` for `ToolContext`, `None` for arguments
+fn context_type(ty: &Type) -> Option<bool> {
+    let (by_ref, ty) = match ty {
+        Type::Reference(reference) => (true, reference.elem.as_ref()),
+        ty => (false, ty),
+    };
+    match ty {
+        Type::Path(path) if path.path.segments.last()?.ident == "ToolContext" => Some(by_ref),
+        _ => None,
+    }
+}
+
+/// The doc comment lines joined, with the leading space rustdoc adds removed
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(doc.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
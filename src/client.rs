@@ -1,18 +1,78 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::broadcast;
+use url::Url;
+
 use crate::{
+    pagination::Page,
     protocol::{Protocol, ProtocolBuilder, RequestOptions},
     transport::Transport,
     types::{
-        ClientCapabilities, Implementation, InitializeRequest, InitializeResponse,
-        LATEST_PROTOCOL_VERSION,
+        CallToolRequest, CallToolResponse, ClientCapabilities, CompleteRequest, CompleteResponse,
+        Completion, CompletionArgument, CompletionReference, ErrorCode, GetPromptRequest,
+        GetPromptResponse, Implementation, InitializeRequest, InitializeResponse, ListRequest,
+        LoggingLevel, PromptDefinition, PromptsListResponse, ProtocolError, ProtocolVersion,
+        ReadResourceRequest, ReadResourceResponse, Resource, ResourceTemplate,
+        ResourceTemplatesListResponse, ResourceUpdatedNotification, ResourcesListResponse,
+        ServerCapabilities, ServerCapability, SetLevelRequest, SubscribeRequest, ToolDefinition,
+        ToolsListResponse, UnsubscribeRequest, SUPPORTED_PROTOCOL_VERSIONS,
     },
 };
 
 use anyhow::{Context, Result};
 use tracing::debug;
 
-#[derive(Clone)]
+/// What the client learned about the server during initialization
+#[derive(Clone, Default)]
+pub struct ClientState {
+    protocol_version: Option<ProtocolVersion>,
+    server_capabilities: Option<ServerCapabilities>,
+    server_info: Option<Implementation>,
+}
+
+/// Lists fetched from the server, dropped when it reports that they changed
+#[derive(Default)]
+struct ListCache {
+    tools: Option<Vec<ToolDefinition>>,
+    prompts: Option<Vec<PromptDefinition>>,
+    resources: Option<Vec<Resource>>,
+    /// Bumped on every invalidation, a listing fetched across one is not stored
+    generation: u64,
+}
+
+impl ListCache {
+    fn invalidate(&mut self, clear: impl FnOnce(&mut ListCache)) {
+        clear(self);
+        self.generation += 1;
+    }
+}
+
+/// How many resource updates are buffered for slow receivers before the oldest are dropped
+const RESOURCE_UPDATES_CAPACITY: usize = 64;
+
 pub struct Client<T: Transport> {
     protocol: Protocol<T>,
+    protocol_versions: Vec<ProtocolVersion>,
+    state: Arc<RwLock<ClientState>>,
+    resource_updates: broadcast::Sender<Url>,
+    lists: Arc<RwLock<ListCache>>,
+}
+
+// Implemented by hand, deriving would require the transport itself to be Clone
+impl<T: Transport> Clone for Client<T> {
+    fn clone(&self) -> Self {
+        Self {
+            protocol: self.protocol.clone(),
+            protocol_versions: self.protocol_versions.clone(),
+            state: self.state.clone(),
+            resource_updates: self.resource_updates.clone(),
+            lists: self.lists.clone(),
+        }
+    }
 }
 
 impl<T: Transport> Client<T> {
@@ -21,8 +81,12 @@ impl<T: Transport> Client<T> {
     }
 
     pub async fn initialize(&self, client_info: Implementation) -> Result<InitializeResponse> {
+        let preferred = self
+            .protocol_versions
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No protocol versions configured"))?;
         let request = InitializeRequest {
-            protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            protocol_version: preferred.to_string(),
             capabilities: ClientCapabilities::default(),
             client_info,
         };
@@ -38,19 +102,46 @@ impl<T: Transport> Client<T> {
         let response: InitializeResponse =
             serde_json::from_value(response).context("Failed to parse initialize response")?;
 
-        if response.protocol_version != LATEST_PROTOCOL_VERSION {
-            return Err(anyhow::anyhow!(
-                "Unsupported protocol version: expected {}, got {}",
-                LATEST_PROTOCOL_VERSION,
-                response.protocol_version
-            ));
-        }
+        let protocol_version = response
+            .protocol_version
+            .parse::<ProtocolVersion>()
+            .ok()
+            .filter(|version| self.protocol_versions.contains(version))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unsupported protocol version: server chose {}, client supports {:?}",
+                    response.protocol_version,
+                    self.protocol_versions
+                        .iter()
+                        .map(ProtocolVersion::as_str)
+                        .collect::<Vec<_>>()
+                )
+            })?;
 
         debug!(
             "Initialized with protocol version: {}",
             response.protocol_version
         );
 
+        {
+            let mut state = self
+                .state
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            state.protocol_version = Some(protocol_version);
+            state.server_capabilities = Some(response.capabilities.clone());
+            state.server_info = Some(response.server_info.clone());
+        }
+        // lists of an earlier session may not match what this server offers
+        self.lists
+            .write()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?
+            .invalidate(|lists| {
+                lists.tools = None;
+                lists.prompts = None;
+                lists.resources = None;
+            });
+
         self.protocol
             .notify("notifications/initialized", None)
             .context("Failed to send initialized notification")?;
@@ -58,12 +149,26 @@ impl<T: Transport> Client<T> {
         Ok(response)
     }
 
+    /// The protocol version agreed on during initialization
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.state.read().ok()?.protocol_version
+    }
+
+    pub fn get_server_capabilities(&self) -> Option<ServerCapabilities> {
+        self.state.read().ok()?.server_capabilities.clone()
+    }
+
+    pub fn get_server_info(&self) -> Option<Implementation> {
+        self.state.read().ok()?.server_info.clone()
+    }
+
     pub async fn request(
         &self,
         method: &str,
         params: Option<serde_json::Value>,
         options: RequestOptions,
     ) -> Result<serde_json::Value> {
+        self.ensure_supported(method)?;
         let response = self
             .protocol
             .request(method, params, options)
@@ -81,6 +186,226 @@ impl<T: Transport> Client<T> {
         })
     }
 
+    /// Cached until the server sends `notifications/tools/list_changed`
+    pub async fn list_tools(&self) -> Result<Vec<ToolDefinition>> {
+        let (tools, generation) = self.cached(|lists| &lists.tools)?;
+        if let Some(tools) = tools {
+            return Ok(tools);
+        }
+        let tools = self.list_all::<ToolsListResponse>("tools/list").await?;
+        self.cache(generation, |lists| &mut lists.tools, tools)
+    }
+
+    /// Send a typed request and parse the result
+    async fn send<R: DeserializeOwned>(&self, method: &str, params: impl Serialize) -> Result<R> {
+        let response = self
+            .request(
+                method,
+                Some(serde_json::to_value(params)?),
+                RequestOptions::default(),
+            )
+            .await?;
+        serde_json::from_value(response)
+            .with_context(|| format!("Failed to parse {} response", method))
+    }
+
+    /// A failing tool is reported through `is_error`, not as an error
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<serde_json::Value>,
+    ) -> Result<CallToolResponse> {
+        let request = CallToolRequest {
+            name: name.to_string(),
+            arguments,
+            meta: None,
+        };
+        self.send("tools/call", request).await
+    }
+
+    /// Definition of a single tool, its annotations tell hosts whether calls need approval
+    pub async fn tool(&self, name: &str) -> Result<Option<ToolDefinition>> {
+        let tools = self.list_tools().await?;
+        Ok(tools.into_iter().find(|tool| tool.name == name))
+    }
+
+    /// Cached until the server sends `notifications/prompts/list_changed`
+    pub async fn list_prompts(&self) -> Result<Vec<PromptDefinition>> {
+        let (prompts, generation) = self.cached(|lists| &lists.prompts)?;
+        if let Some(prompts) = prompts {
+            return Ok(prompts);
+        }
+        let prompts = self.list_all::<PromptsListResponse>("prompts/list").await?;
+        self.cache(generation, |lists| &mut lists.prompts, prompts)
+    }
+
+    pub async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: Option<HashMap<String, String>>,
+    ) -> Result<GetPromptResponse> {
+        let request = GetPromptRequest {
+            name: name.to_string(),
+            arguments,
+            meta: None,
+        };
+        self.send("prompts/get", request).await
+    }
+
+    /// Cached until the server sends `notifications/resources/list_changed`
+    pub async fn list_resources(&self) -> Result<Vec<Resource>> {
+        let (resources, generation) = self.cached(|lists| &lists.resources)?;
+        if let Some(resources) = resources {
+            return Ok(resources);
+        }
+        let resources = self
+            .list_all::<ResourcesListResponse>("resources/list")
+            .await?;
+        self.cache(generation, |lists| &mut lists.resources, resources)
+    }
+
+    pub async fn list_resource_templates(&self) -> Result<Vec<ResourceTemplate>> {
+        self.list_all::<ResourceTemplatesListResponse>("resources/templates/list")
+            .await
+    }
+
+    pub async fn read_resource(&self, uri: &Url) -> Result<ReadResourceResponse> {
+        let request = ReadResourceRequest {
+            uri: uri.clone(),
+            meta: None,
+        };
+        self.send("resources/read", request).await
+    }
+
+    /// Suggestions for the argument `name` of a prompt or resource template, given what was typed so far
+    pub async fn complete(
+        &self,
+        reference: CompletionReference,
+        name: &str,
+        value: &str,
+    ) -> Result<Completion> {
+        let request = CompleteRequest {
+            reference,
+            argument: CompletionArgument {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+            context: None,
+            meta: None,
+        };
+        let response: CompleteResponse = self.send("completion/complete", request).await?;
+        Ok(response.completion)
+    }
+
+    /// Only log messages at least this severe are sent by the server
+    pub async fn set_logging_level(&self, level: LoggingLevel) -> Result<()> {
+        let request = SetLevelRequest { level, meta: None };
+        self.send::<serde_json::Value>("logging/setLevel", request)
+            .await?;
+        Ok(())
+    }
+
+    /// Fetch every page of a list endpoint, following `nextCursor` until the last page
+    pub async fn list_all<P: Page>(&self, method: &str) -> Result<Vec<P::Item>> {
+        let mut items = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = ListRequest {
+                cursor: cursor.take(),
+                meta: None,
+            };
+            let page: P = self.send(method, &request).await?;
+            let next_cursor = page.next_cursor().map(str::to_string);
+            items.extend(page.into_items());
+            match next_cursor {
+                // a server repeating the cursor would otherwise keep us here forever
+                Some(next) if request.cursor.as_ref() != Some(&next) => cursor = Some(next),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// The cached list if there is one, and the generation to pass to `cache` otherwise
+    fn cached<I: Clone>(
+        &self,
+        list: impl FnOnce(&ListCache) -> &Option<Vec<I>>,
+    ) -> Result<(Option<Vec<I>>, u64)> {
+        let lists = self
+            .lists
+            .read()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        Ok((list(&lists).clone(), lists.generation))
+    }
+
+    /// Only stored if nothing was invalidated since `generation` was read,
+    /// the items may predate the change the server reported
+    fn cache<I: Clone>(
+        &self,
+        generation: u64,
+        list: impl FnOnce(&mut ListCache) -> &mut Option<Vec<I>>,
+        items: Vec<I>,
+    ) -> Result<Vec<I>> {
+        let mut lists = self
+            .lists
+            .write()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        if lists.generation == generation {
+            *list(&mut lists) = Some(items.clone());
+        }
+        Ok(items)
+    }
+
+    /// Ask the server to send `notifications/resources/updated` when `uri` changes
+    pub async fn subscribe(&self, uri: &Url) -> Result<()> {
+        let request = SubscribeRequest {
+            uri: uri.clone(),
+            meta: None,
+        };
+        self.send::<serde_json::Value>("resources/subscribe", request)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&self, uri: &Url) -> Result<()> {
+        let request = UnsubscribeRequest {
+            uri: uri.clone(),
+            meta: None,
+        };
+        self.send::<serde_json::Value>("resources/unsubscribe", request)
+            .await?;
+        Ok(())
+    }
+
+    /// Stream of uris of subscribed resources the server reported as updated
+    /// updates are only delivered while the client is listening, see [`Client::start`]
+    pub fn resource_updates(&self) -> broadcast::Receiver<Url> {
+        self.resource_updates.subscribe()
+    }
+
+    /// Refuse methods whose capability the server did not advertise
+    /// before initialization the capabilities are unknown and every method is allowed
+    fn ensure_supported(&self, method: &str) -> Result<()> {
+        let Some(capability) = ServerCapability::required_for(method) else {
+            return Ok(());
+        };
+        let state = self
+            .state
+            .read()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        match &state.server_capabilities {
+            Some(capabilities) if !capabilities.supports(capability) => Err(ProtocolError::new(
+                ErrorCode::MethodNotFound,
+                format!(
+                    "Server does not support '{}': the '{}' capability was not advertised",
+                    method,
+                    capability.as_str()
+                ),
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
     pub async fn start(&self) -> Result<()> {
         self.protocol
             .listen()
@@ -91,18 +416,269 @@ impl<T: Transport> Client<T> {
 
 pub struct ClientBuilder<T: Transport> {
     protocol: ProtocolBuilder<T>,
+    protocol_versions: Vec<ProtocolVersion>,
 }
 
 impl<T: Transport> ClientBuilder<T> {
     pub fn new(transport: T) -> Self {
         Self {
             protocol: ProtocolBuilder::new(transport),
+            protocol_versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+        }
+    }
+
+    /// Protocol versions the client accepts, preferred first
+    /// the preferred version is requested during initialization
+    pub fn protocol_versions(
+        mut self,
+        versions: impl IntoIterator<Item = ProtocolVersion>,
+    ) -> Self {
+        self.protocol_versions = versions.into_iter().collect();
+        self
+    }
+
+    fn invalidate(
+        lists: Arc<RwLock<ListCache>>,
+        clear: impl Fn(&mut ListCache) + Send + Sync + 'static,
+    ) -> impl Fn(()) -> Result<()> + Send + Sync + 'static {
+        move |_| {
+            lists
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?
+                .invalidate(&clear);
+            Ok(())
         }
     }
 
     pub fn build(self) -> Client<T> {
+        let (resource_updates, _) = broadcast::channel(RESOURCE_UPDATES_CAPACITY);
+        let sender = resource_updates.clone();
+        let lists: Arc<RwLock<ListCache>> = Default::default();
+        let protocol = self
+            .protocol
+            .notification_handler(
+                "notifications/resources/updated",
+                move |notification: ResourceUpdatedNotification| {
+                    // no receiver just means nobody is watching updates right now
+                    let _ = sender.send(notification.uri);
+                    Ok(())
+                },
+            )
+            .notification_handler(
+                "notifications/tools/list_changed",
+                Self::invalidate(lists.clone(), |lists| lists.tools = None),
+            )
+            .notification_handler(
+                "notifications/prompts/list_changed",
+                Self::invalidate(lists.clone(), |lists| lists.prompts = None),
+            )
+            .notification_handler(
+                "notifications/resources/list_changed",
+                Self::invalidate(lists.clone(), |lists| lists.resources = None),
+            );
         Client {
-            protocol: self.protocol.build(),
+            protocol: protocol.build(),
+            protocol_versions: self.protocol_versions,
+            state: Default::default(),
+            resource_updates,
+            lists,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{JsonRpcMessage, JsonRpcNotification, JsonRpcRequest, QueueTransport};
+    use crate::types::ToolResponseContent;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_resource_updates() {
+        let notification = JsonRpcMessage::Notification(JsonRpcNotification {
+            method: "notifications/resources/updated".to_string(),
+            params: Some(json!({"uri": "file:///var/log/app.log"})),
+            ..Default::default()
+        });
+        let client = ClientBuilder::new(QueueTransport::new(vec![notification])).build();
+        let mut updates = client.resource_updates();
+        assert!(client.start().await.is_err());
+        assert_eq!(
+            updates.try_recv().unwrap(),
+            Url::parse("file:///var/log/app.log").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_changed_invalidates_cache() {
+        let notification = JsonRpcMessage::Notification(JsonRpcNotification {
+            method: "notifications/tools/list_changed".to_string(),
+            ..Default::default()
+        });
+        let client = ClientBuilder::new(QueueTransport::new(vec![notification])).build();
+        {
+            let mut lists = client.lists.write().unwrap();
+            lists.tools = Some(vec![]);
+            lists.prompts = Some(vec![]);
+        }
+        assert_eq!(client.list_tools().await.unwrap().len(), 0);
+        // a listing fetched while the notification arrives is already outdated
+        let (_, generation) = client.cached(|lists| &lists.resources).unwrap();
+        assert!(client.start().await.is_err());
+        client
+            .cache(generation, |lists| &mut lists.resources, vec![])
+            .unwrap();
+        let lists = client.lists.read().unwrap();
+        assert!(lists.tools.is_none());
+        assert!(lists.prompts.is_some());
+        assert!(lists.resources.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_unadvertised_methods() {
+        let client = ClientBuilder::new(QueueTransport::default()).build();
+        client.state.write().unwrap().server_capabilities = Some(ServerCapabilities {
+            resources: Some(Default::default()),
+            ..Default::default()
+        });
+        let error = client
+            .subscribe(&Url::parse("file:///var/log/app.log").unwrap())
+            .await
+            .unwrap_err();
+        let error = error.downcast::<ProtocolError>().unwrap();
+        assert_eq!(error.code, ErrorCode::MethodNotFound);
+        assert!(error.message.contains("resources.subscribe"));
+    }
+
+    fn reply(request: &JsonRpcRequest) -> serde_json::Value {
+        let params = request.params.clone().unwrap_or_default();
+        match request.method.as_str() {
+            "tools/call" => {
+                json!({"content": [{"type": "text", "text": params["arguments"]["text"]}]})
+            }
+            "prompts/get" => json!({
+                "messages": [{"role": "user", "content": {"type": "text", "text": params["name"]}}]
+            }),
+            "resources/read" => json!({"contents": [{"uri": params["uri"], "text": "hello"}]}),
+            "resources/templates/list" if params.get("cursor").is_none() => json!({
+                "resourceTemplates": [{"uriTemplate": "file:///logs/{name}", "name": "logs"}],
+                "nextCursor": "00000001"
+            }),
+            "resources/templates/list" => json!({
+                "resourceTemplates": [{"uriTemplate": "file:///notes/{name}", "name": "notes"}]
+            }),
+            "completion/complete" => json!({"completion": {"values": ["alpha"], "hasMore": false}}),
+            "initialize" => json!({
+                "protocolVersion": params["protocolVersion"],
+                "capabilities": {},
+                "serverInfo": {"name": "test", "version": "1.0"}
+            }),
+            _ => json!({}),
         }
     }
+
+    /// Listen until `request` completes, the queue runs dry between the pages of a listing
+    async fn answered<F: std::future::Future>(
+        client: &Client<QueueTransport>,
+        request: F,
+    ) -> F::Output {
+        tokio::pin!(request);
+        loop {
+            tokio::select! {
+                biased;
+                output = &mut request => return output,
+                _ = client.start() => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_resets_cache() {
+        let client = ClientBuilder::new(QueueTransport::default().with_responder(reply)).build();
+        client.lists.write().unwrap().tools = Some(vec![]);
+        let client_info = Implementation {
+            name: "test".to_string(),
+            version: "1.0".to_string(),
+        };
+        answered(&client, client.initialize(client_info))
+            .await
+            .unwrap();
+        assert!(client.lists.read().unwrap().tools.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_typed_requests() {
+        let transport = QueueTransport::default().with_responder(reply);
+        let outgoing = transport.outgoing.clone();
+        let client = ClientBuilder::new(transport).build();
+
+        let response = answered(
+            &client,
+            client.call_tool("echo", Some(json!({"text": "hi"}))),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            &response.content[0],
+            ToolResponseContent::Text { text, .. } if text == "hi"
+        ));
+
+        let prompt = answered(&client, client.get_prompt("greeting", None))
+            .await
+            .unwrap();
+        assert_eq!(prompt.messages.len(), 1);
+
+        let uri = Url::parse("file:///notes/today").unwrap();
+        let resource = answered(&client, client.read_resource(&uri)).await.unwrap();
+        assert_eq!(resource.contents[0].uri(), &uri);
+
+        let templates = answered(&client, client.list_resource_templates())
+            .await
+            .unwrap();
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["logs", "notes"]);
+
+        let reference = CompletionReference::Prompt {
+            name: "greeting".to_string(),
+        };
+        let completion = answered(&client, client.complete(reference, "name", "al"))
+            .await
+            .unwrap();
+        assert_eq!(completion.values, ["alpha"]);
+
+        answered(&client, client.set_logging_level(LoggingLevel::Warning))
+            .await
+            .unwrap();
+
+        let sent: Vec<JsonRpcRequest> = outgoing
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|message| match message {
+                JsonRpcMessage::Request(request) => Some(request.clone()),
+                _ => None,
+            })
+            .collect();
+        let methods: Vec<&str> = sent.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(
+            methods,
+            [
+                "tools/call",
+                "prompts/get",
+                "resources/read",
+                "resources/templates/list",
+                "resources/templates/list",
+                "completion/complete",
+                "logging/setLevel",
+            ]
+        );
+        assert_eq!(
+            sent[5].params,
+            Some(json!({
+                "ref": {"type": "ref/prompt", "name": "greeting"},
+                "argument": {"name": "name", "value": "al"}
+            }))
+        );
+        assert_eq!(sent[6].params, Some(json!({"level": "warning"})));
+    }
 }
@@ -1,6 +1,24 @@
 pub mod client;
+pub mod pagination;
+pub mod prompts;
 pub mod protocol;
+pub mod registry;
+pub mod resources;
+pub mod schema;
 pub mod server;
 pub mod tools;
 pub mod transport;
 pub mod types;
+
+// lets the code generated by `#[tool]` refer to `::mcp_sdk` inside this crate too
+extern crate self as mcp_sdk;
+
+/// Dependencies of the code generated by `mcp-sdk-macros`, not a public api
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use async_trait;
+    pub use schemars;
+    pub use serde;
+    pub use serde_json;
+}
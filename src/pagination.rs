@@ -0,0 +1,199 @@
+use crate::types::{
+    ErrorCode, PromptDefinition, PromptsListResponse, ProtocolError, Resource, ResourceTemplate,
+    ResourceTemplatesListResponse, ResourcesListResponse, ToolDefinition, ToolsListResponse,
+};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// An item of a listing, identified by a key that is unique within it
+pub trait PageItem {
+    fn key(&self) -> &str;
+}
+
+impl PageItem for ToolDefinition {
+    fn key(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PageItem for PromptDefinition {
+    fn key(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PageItem for Resource {
+    fn key(&self) -> &str {
+        self.uri.as_str()
+    }
+}
+
+impl PageItem for ResourceTemplate {
+    fn key(&self) -> &str {
+        &self.uri_template
+    }
+}
+
+/// Where the next page starts, encoded into an opaque string for the client
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    /// Key of the last item of the previous page
+    after: String,
+    /// Position of that item, where the next page starts if the item was removed meanwhile
+    index: usize,
+}
+
+/// Cut one page out of a listing
+/// the cursor points after the last item returned, so it stays valid while the registry
+/// changes, items added or removed in between may be missed, servers announce those
+/// with `list_changed`
+pub fn paginate<I: PageItem>(
+    items: Vec<I>,
+    cursor: Option<&str>,
+    page_size: Option<usize>,
+) -> Result<(Vec<I>, Option<String>)> {
+    let start = match cursor {
+        Some(cursor) => {
+            let cursor = decode_cursor(cursor).ok_or_else(|| {
+                ProtocolError::new(
+                    ErrorCode::InvalidParams,
+                    format!("Invalid cursor: {cursor}"),
+                )
+            })?;
+            match items.iter().position(|item| item.key() == cursor.after) {
+                Some(position) => position + 1,
+                // whatever followed the removed item moved up into its place
+                None => cursor.index.min(items.len()),
+            }
+        }
+        None => 0,
+    };
+    let Some(page_size) = page_size else {
+        return Ok((items.into_iter().skip(start).collect(), None));
+    };
+    let end = start.saturating_add(page_size.max(1));
+    let next_cursor = match items.get(end.saturating_sub(1)) {
+        Some(last) if end < items.len() => Some(encode_cursor(&Cursor {
+            after: last.key().to_string(),
+            index: end - 1,
+        })?),
+        _ => None,
+    };
+    let page = items.into_iter().skip(start).take(end - start).collect();
+    Ok((page, next_cursor))
+}
+
+fn encode_cursor(cursor: &Cursor) -> Result<String> {
+    Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor)?))
+}
+
+fn decode_cursor(cursor: &str) -> Option<Cursor> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// A list response that may continue on another page
+pub trait Page: DeserializeOwned {
+    type Item;
+
+    fn next_cursor(&self) -> Option<&str>;
+    fn into_items(self) -> Vec<Self::Item>;
+}
+
+impl Page for ToolsListResponse {
+    type Item = ToolDefinition;
+
+    fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<ToolDefinition> {
+        self.tools
+    }
+}
+
+impl Page for PromptsListResponse {
+    type Item = PromptDefinition;
+
+    fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<PromptDefinition> {
+        self.prompts
+    }
+}
+
+impl Page for ResourcesListResponse {
+    type Item = Resource;
+
+    fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Resource> {
+        self.resources
+    }
+}
+
+impl Page for ResourceTemplatesListResponse {
+    type Item = ResourceTemplate;
+
+    fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<ResourceTemplate> {
+        self.resource_templates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl PageItem for String {
+        fn key(&self) -> &str {
+            self
+        }
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).into();
+        let page = |cursor: Option<&str>| paginate(items.clone(), cursor, Some(2));
+
+        let (first, cursor) = page(None).unwrap();
+        assert_eq!(first, ["a", "b"]);
+        let (second, cursor) = page(cursor.as_deref()).unwrap();
+        assert_eq!(second, ["c", "d"]);
+        let after_d = cursor.clone().unwrap();
+        let (third, cursor) = page(cursor.as_deref()).unwrap();
+        assert_eq!(third, ["e"]);
+        assert!(cursor.is_none());
+
+        let (all, cursor) = paginate(items.clone(), None, None).unwrap();
+        assert_eq!(all.len(), 5);
+        assert!(cursor.is_none());
+
+        // cursors are not offsets, so changing earlier items does not shift the page
+        let mut changed = items.clone();
+        changed.remove(0);
+        changed.insert(1, "x".to_string());
+        let (rest, _) = paginate(changed, Some(&after_d), None).unwrap();
+        assert_eq!(rest, ["e"]);
+
+        // when the item the cursor points after is gone, the page starts where it was
+        let mut without_d = items.clone();
+        without_d.remove(3);
+        let (rest, _) = paginate(without_d, Some(&after_d), None).unwrap();
+        assert_eq!(rest, ["e"]);
+
+        for invalid in ["not a cursor", "00000002"] {
+            let error = page(Some(invalid)).unwrap_err();
+            let error = error.downcast::<ProtocolError>().unwrap();
+            assert_eq!(error.code, ErrorCode::InvalidParams);
+        }
+    }
+}
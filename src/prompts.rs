@@ -0,0 +1,172 @@
+use crate::types::{
+    ErrorCode, GetPromptRequest, GetPromptResponse, PromptArgument, PromptDefinition, ProtocolError,
+};
+use anyhow::Result;
+use std::{collections::HashMap, sync::Arc};
+
+pub trait Prompt: Send + Sync + 'static {
+    fn name(&self) -> String;
+    fn description(&self) -> String;
+    fn arguments(&self) -> Vec<PromptArgument> {
+        vec![]
+    }
+    /// Render the prompt, required arguments are checked before this is called
+    fn get(&self, arguments: HashMap<String, String>) -> Result<GetPromptResponse>;
+    fn as_definition(&self) -> PromptDefinition {
+        let arguments = self.arguments();
+        PromptDefinition {
+            name: self.name(),
+            description: Some(self.description()),
+            arguments: (!arguments.is_empty()).then_some(arguments),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Prompts {
+    prompts: HashMap<String, Arc<dyn Prompt>>,
+    /// Names in registration order
+    order: Vec<String>,
+}
+
+impl Prompts {
+    /// Replacing a prompt with the same name keeps its original position
+    pub fn add_prompt(&mut self, prompt: impl Prompt) {
+        let name = prompt.name();
+        if self
+            .prompts
+            .insert(name.clone(), Arc::new(prompt))
+            .is_none()
+        {
+            self.order.push(name);
+        }
+    }
+
+    /// Returns whether a prompt with that name was registered
+    pub fn remove_prompt(&mut self, name: &str) -> bool {
+        self.order.retain(|registered| registered != name);
+        self.prompts.remove(name).is_some()
+    }
+
+    /// In registration order, so listings are stable
+    pub fn list_prompts(&self) -> Vec<PromptDefinition> {
+        self.order
+            .iter()
+            .map(|name| self.prompts[name].as_definition())
+            .collect()
+    }
+
+    /// Unknown prompts and missing required arguments are invalid params errors
+    pub fn get_prompt(&self, request: GetPromptRequest) -> Result<GetPromptResponse> {
+        let prompt = self.prompts.get(&request.name).ok_or_else(|| {
+            ProtocolError::new(
+                ErrorCode::InvalidParams,
+                format!("Prompt {} not found", request.name),
+            )
+        })?;
+        let arguments = request.arguments.unwrap_or_default();
+        let missing: Vec<String> = prompt
+            .arguments()
+            .into_iter()
+            .filter(|arg| arg.required.unwrap_or(false) && !arguments.contains_key(&arg.name))
+            .map(|arg| arg.name)
+            .collect();
+        if !missing.is_empty() {
+            return Err(ProtocolError::new(
+                ErrorCode::InvalidParams,
+                format!(
+                    "Missing required arguments for prompt {}: {}",
+                    request.name,
+                    missing.join(", ")
+                ),
+            )
+            .into());
+        }
+        prompt.get(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PromptMessage;
+
+    struct ReviewCode;
+
+    impl Prompt for ReviewCode {
+        fn name(&self) -> String {
+            "review_code".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Review a piece of code".to_string()
+        }
+
+        fn arguments(&self) -> Vec<PromptArgument> {
+            vec![
+                PromptArgument {
+                    name: "code".to_string(),
+                    description: None,
+                    required: Some(true),
+                },
+                PromptArgument {
+                    name: "language".to_string(),
+                    description: None,
+                    required: None,
+                },
+            ]
+        }
+
+        fn get(&self, arguments: HashMap<String, String>) -> Result<GetPromptResponse> {
+            Ok(GetPromptResponse {
+                description: None,
+                messages: vec![PromptMessage::user(format!(
+                    "Please review:\n{}",
+                    arguments["code"]
+                ))],
+                meta: None,
+            })
+        }
+    }
+
+    fn request(name: &str, arguments: &[(&str, &str)]) -> GetPromptRequest {
+        GetPromptRequest {
+            name: name.to_string(),
+            arguments: Some(
+                arguments
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            meta: None,
+        }
+    }
+
+    fn error_code(error: anyhow::Error) -> ErrorCode {
+        error.downcast::<ProtocolError>().unwrap().code
+    }
+
+    #[test]
+    fn test_get_prompt() {
+        let mut prompts = Prompts::default();
+        prompts.add_prompt(ReviewCode);
+
+        let definitions = prompts.list_prompts();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].arguments.as_ref().unwrap().len(), 2);
+
+        let response = prompts
+            .get_prompt(request("review_code", &[("code", "fn main() {}")]))
+            .unwrap();
+        assert_eq!(response.messages.len(), 1);
+
+        let missing = prompts
+            .get_prompt(request("review_code", &[("language", "rust")]))
+            .unwrap_err();
+        assert!(missing.to_string().contains("code"));
+        assert_eq!(error_code(missing), ErrorCode::InvalidParams);
+
+        let unknown = prompts.get_prompt(request("unknown", &[])).unwrap_err();
+        assert_eq!(error_code(unknown), ErrorCode::InvalidParams);
+    }
+}
@@ -1,13 +1,14 @@
 use super::transport::{
     JsonRpcError, JsonRpcMessage, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, Message,
-    Transport,
+    RequestId, Transport,
 };
-use super::types::ErrorCode;
+use super::types::{ErrorCode, ProtocolError};
 use anyhow::Result;
 use anyhow::anyhow;
 use async_trait::async_trait;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use std::future::Future;
 use std::sync::atomic::Ordering;
 use std::time::Duration;
 use std::{
@@ -16,19 +17,42 @@ use std::{
 };
 use tokio::sync::Mutex;
 use tokio::sync::oneshot;
+use tokio::task::JoinSet;
 use tokio::time::timeout;
 use tracing::debug;
 
-#[derive(Clone)]
 pub struct Protocol<T: Transport> {
     transport: Arc<T>,
 
     request_id: Arc<AtomicU64>,
     pending_requests: Arc<Mutex<HashMap<u64, oneshot::Sender<JsonRpcResponse>>>>,
-    request_handlers: Arc<Mutex<HashMap<String, Box<dyn RequestHandler>>>>,
+    request_handlers: Arc<Mutex<HashMap<String, Arc<dyn RequestHandler>>>>,
     notification_handlers: Arc<Mutex<HashMap<String, Box<dyn NotificationHandler>>>>,
+    request_guard: Option<Arc<RequestGuard>>,
+    /// Requests being handled on their own task, by id
+    in_flight: Arc<std::sync::Mutex<HashMap<RequestId, CancellationToken>>>,
+    shutdown_grace: Duration,
 }
 
+// Implemented by hand, deriving would require the transport itself to be Clone
+impl<T: Transport> Clone for Protocol<T> {
+    fn clone(&self) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            request_id: self.request_id.clone(),
+            pending_requests: self.pending_requests.clone(),
+            request_handlers: self.request_handlers.clone(),
+            notification_handlers: self.notification_handlers.clone(),
+            request_guard: self.request_guard.clone(),
+            in_flight: self.in_flight.clone(),
+            shutdown_grace: self.shutdown_grace,
+        }
+    }
+}
+
+/// Check run before dispatching any request, an error rejects the request
+pub type RequestGuard = dyn Fn(&JsonRpcRequest) -> Result<()> + Send + Sync;
+
 impl<T: Transport> Protocol<T> {
     pub fn builder(transport: T) -> ProtocolBuilder<T> {
         ProtocolBuilder::new(transport)
@@ -86,12 +110,39 @@ impl<T: Transport> Protocol<T> {
         }
     }
 
+    /// Requests with an async handler are handled on their own task, so their responses
+    /// can be sent out of order, the ones still running get the shutdown grace period
+    /// to finish before they are cancelled
     pub async fn listen(&self) -> Result<()> {
         debug!("Listening for requests");
+        let mut tasks = JoinSet::new();
+        let result = self.receive_loop(&mut tasks).await;
+        self.shutdown(tasks).await;
+        result
+    }
+
+    async fn shutdown(&self, mut tasks: JoinSet<()>) {
+        let finished = async { while tasks.join_next().await.is_some() {} };
+        if timeout(self.shutdown_grace, finished).await.is_ok() {
+            return;
+        }
+        debug!(
+            "Cancelling {} requests still running at shutdown",
+            tasks.len()
+        );
+        for (_, cancellation) in self.lock_in_flight().drain() {
+            cancellation.cancel();
+        }
+        tasks.abort_all();
+        while tasks.join_next().await.is_some() {}
+    }
+
+    async fn receive_loop(&self, tasks: &mut JoinSet<()>) -> Result<()> {
         loop {
             let message: Message = self.transport.receive()?;
+            while tasks.try_join_next().is_some() {}
             match message {
-                JsonRpcMessage::Request(request) => self.handle_request(request).await?,
+                JsonRpcMessage::Request(request) => self.handle_request(request, tasks).await?,
                 JsonRpcMessage::Response(response) => {
                     // Remove and send response through the channel
                     let id = response.id;
@@ -104,6 +155,9 @@ impl<T: Transport> Protocol<T> {
                     }
                 }
                 JsonRpcMessage::Notification(json_rpc_notification) => {
+                    if json_rpc_notification.method == "notifications/cancelled" {
+                        self.cancel(json_rpc_notification.params.as_ref());
+                    }
                     let handlers = self.notification_handlers.lock().await;
                     if let Some(handler) = handlers.get(&json_rpc_notification.method) {
                         handler.handle(json_rpc_notification)?;
@@ -113,26 +167,62 @@ impl<T: Transport> Protocol<T> {
         }
     }
 
-    async fn handle_request(&self, request: JsonRpcRequest) -> Result<()> {
-        let handlers = self.request_handlers.lock().await;
-        match handlers.get(&request.method) {
-            Some(handler) => match handler.handle(request.clone()).await {
+    async fn handle_request(&self, request: JsonRpcRequest, tasks: &mut JoinSet<()>) -> Result<()> {
+        if let Some(guard) = &self.request_guard {
+            if let Err(e) = guard(&request) {
+                let msg = JsonRpcMessage::Response(error_response(request.id, &e));
+                self.transport.send(&msg)?;
+                return Ok(());
+            }
+        }
+        let handler = self
+            .request_handlers
+            .lock()
+            .await
+            .get(&request.method)
+            .cloned();
+        match handler {
+            Some(handler) if handler.is_async() => {
+                let id = request.id;
+                let cancellation = CancellationToken::new();
+                self.lock_in_flight().insert(id, cancellation.clone());
+                let context = RequestContext {
+                    id,
+                    meta: request
+                        .params
+                        .as_ref()
+                        .and_then(|params| params.get("_meta"))
+                        .cloned(),
+                    cancellation: cancellation.clone(),
+                    peer: Peer::new(self.clone()),
+                };
+                let protocol = self.clone();
+                tasks.spawn(async move {
+                    // a cancelled request is dropped and not answered
+                    let response = tokio::select! {
+                        response = handler.handle(request, context) => response,
+                        _ = cancellation.cancelled() => {
+                            protocol.lock_in_flight().remove(&id);
+                            return;
+                        }
+                    };
+                    protocol.lock_in_flight().remove(&id);
+                    let response = response.unwrap_or_else(|e| error_response(id, &e));
+                    if let Err(e) = protocol.transport.send(&JsonRpcMessage::Response(response)) {
+                        debug!("Failed to send response to request {}: {}", id, e);
+                    }
+                });
+            }
+            Some(handler) => match handler
+                .handle(request.clone(), RequestContext::default())
+                .await
+            {
                 Ok(response) => {
                     let msg = JsonRpcMessage::Response(response);
                     self.transport.send(&msg)?;
                 }
                 Err(e) => {
-                    let error_response = JsonRpcResponse {
-                        id: request.id,
-                        result: None,
-                        error: Some(JsonRpcError {
-                            code: ErrorCode::InternalError as i32,
-                            message: e.to_string(),
-                            data: None,
-                        }),
-                        ..Default::default()
-                    };
-                    let msg = JsonRpcMessage::Response(error_response);
+                    let msg = JsonRpcMessage::Response(error_response(request.id, &e));
                     self.transport.send(&msg)?;
                 }
             },
@@ -151,10 +241,208 @@ impl<T: Transport> Protocol<T> {
         }
         Ok(())
     }
+
+    /// Handle `notifications/cancelled` for a request still in flight
+    fn cancel(&self, params: Option<&serde_json::Value>) {
+        let Some(id) = params
+            .and_then(|params| params.get("requestId"))
+            .and_then(serde_json::Value::as_u64)
+        else {
+            return;
+        };
+        if let Some(cancellation) = self.lock_in_flight().remove(&id) {
+            debug!("Request {} cancelled", id);
+            cancellation.cancel();
+        }
+    }
+
+    fn lock_in_flight(&self) -> std::sync::MutexGuard<'_, HashMap<RequestId, CancellationToken>> {
+        // the map stays consistent even if a holder panicked
+        self.in_flight
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// What an async request handler gets besides the params
+#[derive(Clone, Default)]
+pub struct RequestContext {
+    pub id: RequestId,
+    /// `_meta` of the request params
+    pub meta: Option<serde_json::Value>,
+    /// Cancelled when the other side sends `notifications/cancelled` for this request
+    pub cancellation: CancellationToken,
+    pub peer: Peer,
+}
+
+impl RequestContext {
+    /// Reports progress if the request asked for it with a `progressToken`
+    pub fn progress(&self) -> ProgressReporter {
+        ProgressReporter {
+            token: self
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.get("progressToken"))
+                .cloned(),
+            peer: self.peer.clone(),
+        }
+    }
+}
+
+/// Signals that a request was cancelled
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<tokio::sync::watch::Sender<bool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(tokio::sync::watch::Sender::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Completes once the token is cancelled
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.subscribe();
+        // the sender lives as long as self, so waiting cannot fail
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// Sends `notifications/progress` for a request, does nothing if the request has no progress token
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    token: Option<serde_json::Value>,
+    peer: Peer,
+}
+
+impl ProgressReporter {
+    /// `progress` must increase with every call, `total` is omitted if unknown
+    pub fn report(&self, progress: f64, total: Option<f64>, message: Option<&str>) -> Result<()> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let mut params = serde_json::json!({"progressToken": token, "progress": progress});
+        if let Some(total) = total {
+            params["total"] = total.into();
+        }
+        if let Some(message) = message {
+            params["message"] = message.into();
+        }
+        self.peer.notify("notifications/progress", Some(params))
+    }
+}
+
+/// The other side of the connection, for requests such as sampling, roots or elicitation
+/// sent while handling a request, a default peer is not connected and fails every call
+#[derive(Clone, Default)]
+pub struct Peer {
+    protocol: Option<Arc<dyn PeerProtocol>>,
+}
+
+impl Peer {
+    fn new<T: Transport>(protocol: Protocol<T>) -> Self {
+        Self {
+            protocol: Some(Arc::new(protocol)),
+        }
+    }
+
+    fn connected(&self) -> Result<&dyn PeerProtocol> {
+        self.protocol
+            .as_deref()
+            .ok_or_else(|| anyhow!("Not connected to a peer"))
+    }
+
+    pub fn notify(&self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
+        self.connected()?.notify(method, params)
+    }
+
+    /// The result of the request, an error response is returned as an error
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        options: RequestOptions,
+    ) -> Result<serde_json::Value> {
+        let response = self.connected()?.request(method, params, options).await?;
+        match (response.result, response.error) {
+            (Some(result), _) => Ok(result),
+            (None, Some(error)) => Err(anyhow!(
+                "Request '{}' failed: {}: {}",
+                method,
+                error.code,
+                error.message
+            )),
+            (None, None) => Err(anyhow!("Request '{}' failed: Unknown error", method)),
+        }
+    }
+}
+
+/// `Protocol` without its transport type, so peers can be handed to code that is not generic
+#[async_trait]
+trait PeerProtocol: Send + Sync {
+    fn notify(&self, method: &str, params: Option<serde_json::Value>) -> Result<()>;
+    async fn request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        options: RequestOptions,
+    ) -> Result<JsonRpcResponse>;
+}
+
+#[async_trait]
+impl<T: Transport> PeerProtocol for Protocol<T> {
+    fn notify(&self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
+        Protocol::notify(self, method, params)
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        options: RequestOptions,
+    ) -> Result<JsonRpcResponse> {
+        Protocol::request(self, method, params, options).await
+    }
+}
+
+/// Build the error response for a failed request
+/// a `ProtocolError` keeps its code, anything else is reported as an internal error
+fn error_response(id: RequestId, error: &anyhow::Error) -> JsonRpcResponse {
+    let code = error
+        .downcast_ref::<ProtocolError>()
+        .map(|e| e.code)
+        .unwrap_or(ErrorCode::InternalError);
+    JsonRpcResponse {
+        id,
+        result: None,
+        error: Some(JsonRpcError {
+            code: code as i32,
+            message: error.to_string(),
+            data: None,
+        }),
+        ..Default::default()
+    }
 }
 
 /// The default request timeout, in milliseconds
 pub const DEFAULT_REQUEST_TIMEOUT_MSEC: u64 = 60000;
+pub const DEFAULT_SHUTDOWN_GRACE_MSEC: u64 = 5000;
 pub struct RequestOptions {
     timeout: Duration,
 }
@@ -175,8 +463,10 @@ impl Default for RequestOptions {
 
 pub struct ProtocolBuilder<T: Transport> {
     transport: T,
-    request_handlers: HashMap<String, Box<dyn RequestHandler>>,
+    request_handlers: HashMap<String, Arc<dyn RequestHandler>>,
     notification_handlers: HashMap<String, Box<dyn NotificationHandler>>,
+    request_guard: Option<Arc<RequestGuard>>,
+    shutdown_grace: Duration,
 }
 impl<T: Transport> ProtocolBuilder<T> {
     pub fn new(transport: T) -> Self {
@@ -184,8 +474,27 @@ impl<T: Transport> ProtocolBuilder<T> {
             transport,
             request_handlers: HashMap::new(),
             notification_handlers: HashMap::new(),
+            request_guard: None,
+            shutdown_grace: Duration::from_millis(DEFAULT_SHUTDOWN_GRACE_MSEC),
         }
     }
+
+    /// How long requests still running when the transport closes may take to finish
+    /// after that they are cancelled and their tasks aborted
+    pub fn shutdown_grace(mut self, shutdown_grace: Duration) -> Self {
+        self.shutdown_grace = shutdown_grace;
+        self
+    }
+
+    /// Register a check that runs before every request is dispatched
+    /// if it returns an error the request is answered with that error instead
+    pub fn request_guard(
+        mut self,
+        guard: impl Fn(&JsonRpcRequest) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.request_guard = Some(Arc::new(guard));
+        self
+    }
     /// Register a typed request handler
     pub fn request_handler<Req, Resp>(
         mut self,
@@ -202,7 +511,44 @@ impl<T: Transport> ProtocolBuilder<T> {
         };
 
         self.request_handlers
-            .insert(method.to_string(), Box::new(handler));
+            .insert(method.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Register a typed request handler whose response is computed asynchronously
+    /// it runs on its own task, so a slow handler does not hold up other messages
+    pub fn async_request_handler<Req, Resp, Fut>(
+        self,
+        method: &str,
+        handler: impl Fn(Req) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Req: DeserializeOwned + Send + Sync + 'static,
+        Resp: Serialize + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp>> + Send + 'static,
+    {
+        self.request_handler_with_context(method, move |params, _| handler(params))
+    }
+
+    /// Register an async request handler that also gets the request id, `_meta`,
+    /// a cancellation token and the peer to send requests back to
+    pub fn request_handler_with_context<Req, Resp, Fut>(
+        mut self,
+        method: &str,
+        handler: impl Fn(Req, RequestContext) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Req: DeserializeOwned + Send + Sync + 'static,
+        Resp: Serialize + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp>> + Send + 'static,
+    {
+        let handler = AsyncRequestHandler {
+            handler,
+            _phantom: std::marker::PhantomData,
+        };
+
+        self.request_handlers
+            .insert(method.to_string(), Arc::new(handler));
         self
     }
 
@@ -235,6 +581,9 @@ impl<T: Transport> ProtocolBuilder<T> {
             notification_handlers: Arc::new(Mutex::new(self.notification_handlers)),
             request_id: Arc::new(AtomicU64::new(0)),
             pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            request_guard: self.request_guard,
+            in_flight: Default::default(),
+            shutdown_grace: self.shutdown_grace,
         }
     }
 }
@@ -242,7 +591,15 @@ impl<T: Transport> ProtocolBuilder<T> {
 // Wrapper for handler types using async trait
 #[async_trait]
 trait RequestHandler: Send + Sync {
-    async fn handle(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse>;
+    async fn handle(
+        &self,
+        request: JsonRpcRequest,
+        context: RequestContext,
+    ) -> Result<JsonRpcResponse>;
+    /// Async handlers are spawned instead of blocking the listen loop
+    fn is_async(&self) -> bool {
+        false
+    }
 }
 
 trait NotificationHandler: Send + Sync {
@@ -267,13 +624,15 @@ where
     Resp: Serialize + Send + Sync + 'static,
     F: Fn(Req) -> Result<Resp> + Send + Sync + 'static,
 {
-    async fn handle(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+    async fn handle(
+        &self,
+        request: JsonRpcRequest,
+        _context: RequestContext,
+    ) -> Result<JsonRpcResponse> {
         // If params is None or null, deserialize as unit type using Value::Null
-        let params: Req = if request.params.is_none() || request.params.as_ref().unwrap().is_null()
-        {
-            serde_json::from_value(serde_json::Value::Null)?
-        } else {
-            serde_json::from_value(request.params.unwrap())?
+        let params: Req = match request.params {
+            Some(params) if !params.is_null() => serde_json::from_value(params)?,
+            _ => serde_json::from_value(serde_json::Value::Null)?,
         };
         let result = (self.handler)(params)?;
         Ok(JsonRpcResponse {
@@ -284,6 +643,42 @@ where
         })
     }
 }
+struct AsyncRequestHandler<Req, F> {
+    handler: F,
+    _phantom: std::marker::PhantomData<Req>,
+}
+
+#[async_trait]
+impl<Req, Resp, F, Fut> RequestHandler for AsyncRequestHandler<Req, F>
+where
+    Req: DeserializeOwned + Send + Sync + 'static,
+    Resp: Serialize + Send + Sync + 'static,
+    F: Fn(Req, RequestContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Resp>> + Send + 'static,
+{
+    async fn handle(
+        &self,
+        request: JsonRpcRequest,
+        context: RequestContext,
+    ) -> Result<JsonRpcResponse> {
+        let params: Req = match request.params {
+            Some(params) if !params.is_null() => serde_json::from_value(params)?,
+            _ => serde_json::from_value(serde_json::Value::Null)?,
+        };
+        let result = (self.handler)(params, context).await?;
+        Ok(JsonRpcResponse {
+            id: request.id,
+            result: Some(serde_json::to_value(result)?),
+            error: None,
+            ..Default::default()
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
 pub struct TypedNotificationHandler<N, F>
 where
     N: DeserializeOwned + Send + Sync + 'static,
@@ -300,12 +695,10 @@ where
     F: Fn(N) -> Result<()> + Send + Sync + 'static,
 {
     fn handle(&self, notification: JsonRpcNotification) -> Result<()> {
-        let params: N =
-            if notification.params.is_none() || notification.params.as_ref().unwrap().is_null() {
-                serde_json::from_value(serde_json::Value::Null)?
-            } else {
-                serde_json::from_value(notification.params.unwrap())?
-            };
+        let params: N = match notification.params {
+            Some(params) if !params.is_null() => serde_json::from_value(params)?,
+            _ => serde_json::from_value(serde_json::Value::Null)?,
+        };
         (self.handler)(params)
     }
 }
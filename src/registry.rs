@@ -0,0 +1,134 @@
+use crate::{
+    prompts::Prompts,
+    resources::Resources,
+    tools::{AsyncTool, Tool, Tools, TypedTool},
+};
+use anyhow::Result;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+pub type ToolsHandle = RegistryHandle<Tools>;
+pub type PromptsHandle = RegistryHandle<Prompts>;
+pub type ResourcesHandle = RegistryHandle<Resources>;
+
+type ListChangedSink = dyn Fn() -> Result<()> + Send + Sync;
+
+/// A registry shared by the handlers of a server and its handle
+pub(crate) struct Shared<R> {
+    registry: RwLock<R>,
+}
+
+impl<R> Shared<R> {
+    pub(crate) fn new(registry: R) -> Arc<Self> {
+        Arc::new(Self {
+            registry: RwLock::new(registry),
+        })
+    }
+
+    pub(crate) fn read(&self) -> Result<RwLockReadGuard<'_, R>> {
+        self.registry
+            .read()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))
+    }
+}
+
+/// Shared handle to the tools, prompts or resources of a running server
+/// changes made through [`RegistryHandle::update`] tell the client the list changed
+pub struct RegistryHandle<R> {
+    registry: Arc<Shared<R>>,
+    list_changed: Arc<ListChangedSink>,
+}
+
+// Implemented by hand, deriving would require the registry itself to be Clone
+impl<R> Clone for RegistryHandle<R> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            list_changed: self.list_changed.clone(),
+        }
+    }
+}
+
+impl<R> RegistryHandle<R> {
+    pub(crate) fn new(
+        registry: Arc<Shared<R>>,
+        list_changed: impl Fn() -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            registry,
+            list_changed: Arc::new(list_changed),
+        }
+    }
+
+    pub fn read<O>(&self, f: impl FnOnce(&R) -> O) -> Result<O> {
+        Ok(f(&*self.registry.read()?))
+    }
+
+    /// Mutate the registry, `f` returns its output and whether it changed anything
+    /// the matching `list_changed` notification is only sent for actual changes
+    pub fn update<O>(&self, f: impl FnOnce(&mut R) -> (O, bool)) -> Result<O> {
+        let (output, changed) = {
+            let mut registry = self
+                .registry
+                .registry
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            f(&mut registry)
+        };
+        if changed {
+            (self.list_changed)()?;
+        }
+        Ok(output)
+    }
+}
+
+impl RegistryHandle<Tools> {
+    /// Fails without changes if the name is taken, unless the registry's `on_duplicate` says otherwise
+    /// returns whether the tool was registered, false if the existing one was kept
+    pub fn add_tool(&self, tool: impl Tool) -> Result<bool> {
+        self.update(|tools| added(tools.add_tool(tool)))?
+    }
+
+    pub fn add_typed_tool(&self, tool: impl TypedTool) -> Result<bool> {
+        self.update(|tools| added(tools.add_typed_tool(tool)))?
+    }
+
+    pub fn add_async_tool(&self, tool: impl AsyncTool) -> Result<bool> {
+        self.update(|tools| added(tools.add_async_tool(tool)))?
+    }
+
+    /// A tool with the same name is always replaced, returns whether there was one
+    pub fn replace_tool(&self, tool: impl Tool) -> Result<bool> {
+        self.update(|tools| (tools.replace_tool(tool), true))
+    }
+
+    pub fn replace_typed_tool(&self, tool: impl TypedTool) -> Result<bool> {
+        self.update(|tools| (tools.replace_typed_tool(tool), true))
+    }
+
+    pub fn replace_async_tool(&self, tool: impl AsyncTool) -> Result<bool> {
+        self.update(|tools| (tools.replace_async_tool(tool), true))
+    }
+
+    /// Returns whether a tool with that name was registered
+    pub fn remove_tool(&self, name: &str) -> Result<bool> {
+        self.update(|tools| {
+            let removed = tools.remove_tool(name);
+            (removed, removed)
+        })
+    }
+
+    /// Show or hide a registered tool, the client is only notified when this changes anything
+    /// returns whether a tool with that name is registered
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<bool> {
+        self.update(|tools| {
+            let changed = tools.is_registered(name) && tools.is_enabled(name) != enabled;
+            (tools.set_enabled(name, enabled), changed)
+        })
+    }
+}
+
+/// The registry only changed if the tool was actually registered
+fn added(added: Result<bool>) -> (Result<bool>, bool) {
+    let changed = matches!(added, Ok(true));
+    (added, changed)
+}
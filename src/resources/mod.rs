@@ -0,0 +1,363 @@
+use crate::types::{
+    ErrorCode, ProtocolError, ReadResourceRequest, ReadResourceResponse, Resource,
+    ResourceContents, ResourceTemplate,
+};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+mod subscriptions;
+mod template;
+pub use subscriptions::*;
+pub use template::*;
+
+pub trait ResourceProvider: Send + Sync + 'static {
+    fn uri(&self) -> Url;
+    fn name(&self) -> String;
+    fn description(&self) -> Option<String> {
+        None
+    }
+    /// Defaults to a guess based on the extension of the uri
+    fn mime_type(&self) -> Option<String> {
+        guess_mime_type(&self.uri()).map(str::to_string)
+    }
+    fn read(&self) -> Result<Vec<ResourceContents>>;
+    fn as_resource(&self) -> Resource {
+        Resource {
+            uri: self.uri(),
+            name: self.name(),
+            description: self.description(),
+            mime_type: self.mime_type(),
+            annotations: None,
+        }
+    }
+}
+
+/// Serves every resource whose uri matches a URI template
+pub trait ResourceTemplateHandler: Send + Sync + 'static {
+    /// Variables extracted from the uri, see [`from_variables`] for how they are parsed
+    type Params: DeserializeOwned;
+
+    fn uri_template(&self) -> String;
+    fn name(&self) -> String;
+    fn description(&self) -> Option<String> {
+        None
+    }
+    fn mime_type(&self) -> Option<String> {
+        None
+    }
+    fn read(&self, uri: &Url, params: Self::Params) -> Result<Vec<ResourceContents>>;
+    fn as_resource_template(&self) -> ResourceTemplate {
+        ResourceTemplate {
+            uri_template: self.uri_template(),
+            name: self.name(),
+            description: self.description(),
+            mime_type: self.mime_type(),
+        }
+    }
+}
+
+/// A template handler with its parameters erased so handlers can share a registry
+trait TemplateRoute: Send + Sync {
+    fn template(&self) -> &UriTemplate;
+    fn definition(&self) -> ResourceTemplate;
+    fn mime_type(&self) -> Option<String>;
+    fn read(&self, uri: &Url, variables: HashMap<String, String>) -> Result<Vec<ResourceContents>>;
+}
+
+struct TypedTemplateRoute<H> {
+    template: UriTemplate,
+    handler: H,
+}
+
+impl<H: ResourceTemplateHandler> TemplateRoute for TypedTemplateRoute<H> {
+    fn template(&self) -> &UriTemplate {
+        &self.template
+    }
+
+    fn definition(&self) -> ResourceTemplate {
+        self.handler.as_resource_template()
+    }
+
+    fn mime_type(&self) -> Option<String> {
+        self.handler.mime_type()
+    }
+
+    fn read(&self, uri: &Url, variables: HashMap<String, String>) -> Result<Vec<ResourceContents>> {
+        let params = from_variables(variables).map_err(|e| {
+            ProtocolError::new(
+                ErrorCode::InvalidParams,
+                format!("Invalid parameters in {uri} for {}: {e}", self.template),
+            )
+        })?;
+        self.handler.read(uri, params)
+    }
+}
+
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<Url, Arc<dyn ResourceProvider>>,
+    /// Uris in registration order
+    order: Vec<Url>,
+    templates: Vec<Arc<dyn TemplateRoute>>,
+    updates: ResourceUpdates,
+}
+
+impl Resources {
+    /// Replacing a resource with the same uri keeps its original position
+    pub fn add_resource(&mut self, resource: impl ResourceProvider) {
+        let uri = resource.uri();
+        if self
+            .resources
+            .insert(uri.clone(), Arc::new(resource))
+            .is_none()
+        {
+            self.order.push(uri);
+        }
+    }
+
+    /// Returns whether a resource with that uri was registered
+    pub fn remove_resource(&mut self, uri: &Url) -> bool {
+        self.order.retain(|registered| registered != uri);
+        self.resources.remove(uri).is_some()
+    }
+
+    /// Register a handler for every uri matching its template
+    /// templates are tried in registration order, after the static resources
+    pub fn add_template(&mut self, handler: impl ResourceTemplateHandler) -> Result<()> {
+        let template = UriTemplate::parse(&handler.uri_template())?;
+        self.templates
+            .push(Arc::new(TypedTemplateRoute { template, handler }));
+        Ok(())
+    }
+
+    /// Handle for notifying subscribers that a resource changed
+    pub fn updates(&self) -> ResourceUpdates {
+        self.updates.clone()
+    }
+
+    /// Whether `uri` is a static resource or matches one of the templates
+    pub fn contains(&self, uri: &Url) -> bool {
+        self.resources.contains_key(uri)
+            || self
+                .templates
+                .iter()
+                .any(|route| route.template().matches(uri.as_str()).is_some())
+    }
+
+    pub fn list_templates(&self) -> Vec<ResourceTemplate> {
+        self.templates
+            .iter()
+            .map(|route| route.definition())
+            .collect()
+    }
+
+    /// In registration order, so listings are stable
+    pub fn list_resources(&self) -> Vec<Resource> {
+        self.order
+            .iter()
+            .map(|uri| self.resources[uri].as_resource())
+            .collect()
+    }
+
+    /// Contents without a mime type inherit the one of the resource or template
+    pub fn read_resource(&self, request: ReadResourceRequest) -> Result<ReadResourceResponse> {
+        let (mut contents, mime_type) = match self.resources.get(&request.uri) {
+            Some(resource) => (resource.read()?, resource.mime_type()),
+            None => {
+                let (route, variables) = self
+                    .templates
+                    .iter()
+                    .find_map(|route| {
+                        Some((route, route.template().matches(request.uri.as_str())?))
+                    })
+                    .ok_or_else(|| {
+                        ProtocolError::new(
+                            ErrorCode::ResourceNotFound,
+                            format!("Resource {} not found", request.uri),
+                        )
+                    })?;
+                let mime_type = route
+                    .mime_type()
+                    .or_else(|| guess_mime_type(&request.uri).map(str::to_string));
+                (route.read(&request.uri, variables)?, mime_type)
+            }
+        };
+        for content in contents.iter_mut() {
+            if content.mime_type().is_none() && content.uri() == &request.uri {
+                content.mime_type_mut().clone_from(&mime_type);
+            }
+        }
+        Ok(ReadResourceResponse {
+            contents,
+            meta: None,
+        })
+    }
+}
+
+/// Guess the mime type of a resource from the extension of its path
+pub fn guess_mime_type(uri: &Url) -> Option<&'static str> {
+    let file_name = uri.path_segments()?.next_back()?;
+    let (_, extension) = file_name.rsplit_once('.')?;
+    let mime_type = match extension.to_ascii_lowercase().as_str() {
+        "txt" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Readme;
+
+    impl ResourceProvider for Readme {
+        fn uri(&self) -> Url {
+            Url::parse("file:///project/README.md").unwrap()
+        }
+
+        fn name(&self) -> String {
+            "README".to_string()
+        }
+
+        fn read(&self) -> Result<Vec<ResourceContents>> {
+            Ok(vec![ResourceContents::text(self.uri(), "# Project")])
+        }
+    }
+
+    struct Logo;
+
+    impl ResourceProvider for Logo {
+        fn uri(&self) -> Url {
+            Url::parse("app://assets/logo").unwrap()
+        }
+
+        fn name(&self) -> String {
+            "logo".to_string()
+        }
+
+        fn mime_type(&self) -> Option<String> {
+            Some("image/png".to_string())
+        }
+
+        fn read(&self) -> Result<Vec<ResourceContents>> {
+            Ok(vec![ResourceContents::blob(self.uri(), "iVBORw0KGgo=")])
+        }
+    }
+
+    fn read(resources: &Resources, uri: &str) -> Result<ReadResourceResponse> {
+        resources.read_resource(ReadResourceRequest {
+            uri: Url::parse(uri).unwrap(),
+            meta: None,
+        })
+    }
+
+    #[test]
+    fn test_read_resource() {
+        let mut resources = Resources::default();
+        resources.add_resource(Readme);
+        resources.add_resource(Logo);
+        assert_eq!(resources.list_resources().len(), 2);
+
+        let readme = read(&resources, "file:///project/README.md").unwrap();
+        assert_eq!(readme.contents[0].mime_type(), Some("text/markdown"));
+        assert!(matches!(readme.contents[0], ResourceContents::Text(_)));
+
+        let logo = read(&resources, "app://assets/logo").unwrap();
+        assert_eq!(logo.contents[0].mime_type(), Some("image/png"));
+        assert!(matches!(logo.contents[0], ResourceContents::Blob(_)));
+
+        let missing = read(&resources, "file:///missing.txt").unwrap_err();
+        assert_eq!(
+            missing.downcast::<ProtocolError>().unwrap().code,
+            ErrorCode::ResourceNotFound
+        );
+    }
+
+    struct Rows;
+
+    #[derive(serde::Deserialize)]
+    struct RowParams {
+        table: String,
+        id: u64,
+    }
+
+    impl ResourceTemplateHandler for Rows {
+        type Params = RowParams;
+
+        fn uri_template(&self) -> String {
+            "db://{table}/{id}".to_string()
+        }
+
+        fn name(&self) -> String {
+            "row".to_string()
+        }
+
+        fn mime_type(&self) -> Option<String> {
+            Some("application/json".to_string())
+        }
+
+        fn read(&self, uri: &Url, params: RowParams) -> Result<Vec<ResourceContents>> {
+            let row = serde_json::json!({"table": params.table, "id": params.id});
+            Ok(vec![ResourceContents::text(uri.clone(), row.to_string())])
+        }
+    }
+
+    #[test]
+    fn test_read_template() {
+        let mut resources = Resources::default();
+        resources.add_template(Rows).unwrap();
+        assert_eq!(
+            resources.list_templates()[0].uri_template,
+            "db://{table}/{id}"
+        );
+
+        let row = read(&resources, "db://users/42").unwrap();
+        assert_eq!(row.contents[0].mime_type(), Some("application/json"));
+        let ResourceContents::Text(text) = &row.contents[0] else {
+            panic!("expected text contents");
+        };
+        assert_eq!(text.text, r#"{"id":42,"table":"users"}"#);
+
+        let invalid = read(&resources, "db://users/abc").unwrap_err();
+        assert_eq!(
+            invalid.downcast::<ProtocolError>().unwrap().code,
+            ErrorCode::InvalidParams
+        );
+        let missing = read(&resources, "db://users").unwrap_err();
+        assert_eq!(
+            missing.downcast::<ProtocolError>().unwrap().code,
+            ErrorCode::ResourceNotFound
+        );
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        let guess = |uri: &str| guess_mime_type(&Url::parse(uri).unwrap());
+        assert_eq!(guess("file:///a/b/data.JSON"), Some("application/json"));
+        assert_eq!(
+            guess("https://example.com/image.png?size=2"),
+            Some("image/png")
+        );
+        assert_eq!(guess("file:///a.d/Makefile"), None);
+    }
+}
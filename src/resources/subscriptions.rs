@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::sync::{Arc, RwLock};
+use url::Url;
+
+/// The session a `ResourceUpdates` handle publishes to, it owns the subscriptions
+pub(crate) trait UpdateSink: Send + Sync {
+    fn is_subscribed(&self, uri: &Url) -> bool;
+
+    fn send(&self, uri: &Url) -> Result<()>;
+}
+
+/// Handle for publishing resource updates to the subscribed client
+/// clone it into providers before the server is built, the server connects it on build
+#[derive(Clone, Default)]
+pub struct ResourceUpdates {
+    sink: Arc<RwLock<Option<Arc<dyn UpdateSink>>>>,
+}
+
+impl ResourceUpdates {
+    /// Send `notifications/resources/updated` if the client subscribed to `uri`
+    /// does nothing when there is no subscription or no server is connected yet
+    pub fn notify_updated(&self, uri: &Url) -> Result<()> {
+        match self.sink() {
+            Some(sink) if sink.is_subscribed(uri) => sink.send(uri),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the client of the connected session subscribed to `uri`
+    pub fn is_subscribed(&self, uri: &Url) -> bool {
+        self.sink().is_some_and(|sink| sink.is_subscribed(uri))
+    }
+
+    pub(crate) fn connect(&self, sink: impl UpdateSink + 'static) {
+        if let Ok(mut current) = self.sink.write() {
+            *current = Some(Arc::new(sink));
+        }
+    }
+
+    fn sink(&self) -> Option<Arc<dyn UpdateSink>> {
+        self.sink.read().ok()?.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashSet, sync::Mutex};
+
+    #[derive(Default)]
+    struct Session {
+        subscriptions: HashSet<Url>,
+        sent: Mutex<Vec<Url>>,
+    }
+
+    impl UpdateSink for Arc<Session> {
+        fn is_subscribed(&self, uri: &Url) -> bool {
+            self.subscriptions.contains(uri)
+        }
+
+        fn send(&self, uri: &Url) -> Result<()> {
+            self.sent.lock().unwrap().push(uri.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_notify_only_subscribed() {
+        let updates = ResourceUpdates::default();
+        let uri = Url::parse("file:///log.txt").unwrap();
+        let other = Url::parse("file:///other.txt").unwrap();
+
+        // not connected yet, nothing to do
+        updates.notify_updated(&uri).unwrap();
+        assert!(!updates.is_subscribed(&uri));
+
+        let session = Arc::new(Session {
+            subscriptions: HashSet::from([uri.clone()]),
+            ..Default::default()
+        });
+        updates.connect(session.clone());
+        updates.notify_updated(&uri).unwrap();
+        updates.notify_updated(&other).unwrap();
+
+        assert_eq!(*session.sent.lock().unwrap(), vec![uri]);
+    }
+}
@@ -0,0 +1,767 @@
+//! RFC 6570 URI templates
+//! supports parsing, expansion with string values and matching a uri back into its variables
+//! https://datatracker.ietf.org/doc/html/rfc6570
+use anyhow::Result;
+use serde::de::{self, value::MapDeserializer, IntoDeserializer, Visitor};
+use std::{collections::HashMap, fmt};
+
+/// Longer uris never match a template, matching takes time quadratic in the uri length
+const MAX_MATCH_LENGTH: usize = 8 * 1024;
+
+/// A parsed URI template such as `db://{table}/{id}` or `file:///{+path}{?version}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Expression {
+        operator: Operator,
+        variables: Vec<VarSpec>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VarSpec {
+    name: String,
+    explode: bool,
+    /// `{var:N}` expands to at most the first N characters of the value
+    max_length: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// `{var}`
+    Simple,
+    /// `{+var}`
+    Reserved,
+    /// `{#var}`
+    Fragment,
+    /// `{.var}`
+    Label,
+    /// `{/var}`
+    Path,
+    /// `{;var}`
+    PathParam,
+    /// `{?var}`
+    Query,
+    /// `{&var}`
+    QueryContinuation,
+}
+
+impl Operator {
+    fn parse(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(Operator::Reserved),
+            '#' => Some(Operator::Fragment),
+            '.' => Some(Operator::Label),
+            '/' => Some(Operator::Path),
+            ';' => Some(Operator::PathParam),
+            '?' => Some(Operator::Query),
+            '&' => Some(Operator::QueryContinuation),
+            _ => None,
+        }
+    }
+
+    /// Character written before the first expanded value
+    fn prefix(&self) -> Option<char> {
+        match self {
+            Operator::Simple | Operator::Reserved => None,
+            Operator::Fragment => Some('#'),
+            Operator::Label => Some('.'),
+            Operator::Path => Some('/'),
+            Operator::PathParam => Some(';'),
+            Operator::Query => Some('?'),
+            Operator::QueryContinuation => Some('&'),
+        }
+    }
+
+    /// Character written between expanded values
+    fn separator(&self) -> char {
+        match self {
+            Operator::Simple | Operator::Reserved | Operator::Fragment => ',',
+            Operator::Label => '.',
+            Operator::Path => '/',
+            Operator::PathParam => ';',
+            Operator::Query | Operator::QueryContinuation => '&',
+        }
+    }
+
+    /// Whether values are written as `name=value`
+    fn named(&self) -> bool {
+        matches!(
+            self,
+            Operator::PathParam | Operator::Query | Operator::QueryContinuation
+        )
+    }
+
+    /// Whether reserved characters are kept as is during expansion
+    fn allows_reserved(&self) -> bool {
+        matches!(self, Operator::Reserved | Operator::Fragment)
+    }
+
+    /// Whether `c` may appear inside the expansion of this operator when matching
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Operator::Reserved | Operator::Fragment => true,
+            Operator::Simple | Operator::Label | Operator::PathParam => {
+                !matches!(c, '/' | '?' | '#')
+            }
+            Operator::Path => !matches!(c, '?' | '#'),
+            Operator::Query | Operator::QueryContinuation => c != '#',
+        }
+    }
+}
+
+impl UriTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end = rest.find('}').ok_or_else(|| {
+                        anyhow::anyhow!("Unclosed expression in uri template: {template}")
+                    })?;
+                    parts.push(Self::parse_expression(&rest[1..end], template)?);
+                    rest = &rest[end + 1..];
+                }
+                Some(start) => {
+                    parts.push(Part::Literal(rest[..start].to_string()));
+                    rest = &rest[start..];
+                }
+                None => {
+                    parts.push(Part::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+        if parts
+            .iter()
+            .any(|part| matches!(part, Part::Literal(literal) if literal.contains('}')))
+        {
+            anyhow::bail!("Unmatched '}}' in uri template: {template}");
+        }
+        Ok(Self {
+            template: template.to_string(),
+            parts,
+        })
+    }
+
+    fn parse_expression(expression: &str, template: &str) -> Result<Part> {
+        let mut chars = expression.chars();
+        let (operator, variables) = match chars.next().and_then(Operator::parse) {
+            Some(operator) => (operator, chars.as_str()),
+            None => (Operator::Simple, expression),
+        };
+        let variables = variables
+            .split(',')
+            .map(|spec| {
+                let (name, explode, max_length) = match spec.strip_suffix('*') {
+                    Some(name) => (name, true, None),
+                    None => match spec.split_once(':') {
+                        Some((name, length)) => {
+                            let length = length
+                                .parse()
+                                .ok()
+                                .filter(|length| (1..10000).contains(length))
+                                .ok_or_else(|| {
+                                    anyhow::anyhow!(
+                                        "Invalid prefix modifier '{spec}' in uri template: {template}"
+                                    )
+                                })?;
+                            (name, false, Some(length))
+                        }
+                        None => (spec, false, None),
+                    },
+                };
+                let valid = !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '%'));
+                if !valid {
+                    anyhow::bail!("Invalid variable '{spec}' in uri template: {template}");
+                }
+                Ok(VarSpec {
+                    name: name.to_string(),
+                    explode,
+                    max_length,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Part::Expression {
+            operator,
+            variables,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Names of all variables in the template, in order of appearance
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts
+            .iter()
+            .flat_map(|part| match part {
+                Part::Literal(_) => [].iter(),
+                Part::Expression { variables, .. } => variables.iter(),
+            })
+            .map(|var| var.name.as_str())
+    }
+
+    /// Expand the template, variables without a value are left out
+    pub fn expand(&self, values: &HashMap<String, String>) -> String {
+        let mut uri = String::new();
+        for part in &self.parts {
+            let (operator, variables) = match part {
+                Part::Literal(literal) => {
+                    uri.push_str(literal);
+                    continue;
+                }
+                Part::Expression {
+                    operator,
+                    variables,
+                } => (operator, variables),
+            };
+            let expanded: Vec<String> = variables
+                .iter()
+                .filter_map(|var| {
+                    let value = values.get(&var.name)?;
+                    let value = match var.max_length {
+                        Some(length) => value.chars().take(length).collect(),
+                        None => value.clone(),
+                    };
+                    let value = encode(&value, operator.allows_reserved());
+                    Some(if operator.named() {
+                        format!("{}={}", var.name, value)
+                    } else {
+                        value
+                    })
+                })
+                .collect();
+            if expanded.is_empty() {
+                continue;
+            }
+            if let Some(prefix) = operator.prefix() {
+                uri.push(prefix);
+            }
+            uri.push_str(&expanded.join(&operator.separator().to_string()));
+        }
+        uri
+    }
+
+    /// Match a uri against the template and extract the values of its variables
+    /// values are percent-decoded, variables that were not expanded are absent
+    /// prefix modifiers are not checked, a longer value still matches
+    pub fn matches(&self, uri: &str) -> Option<HashMap<String, String>> {
+        if uri.len() > MAX_MATCH_LENGTH {
+            return None;
+        }
+        let mut values = HashMap::new();
+        let mut failed = Failed {
+            positions: vec![false; (self.parts.len() + 1) * (uri.len() + 1)],
+            stride: uri.len() + 1,
+        };
+        self.match_parts(0, uri, &mut values, &mut failed)
+            .then_some(values)
+    }
+
+    /// Match `input` against the parts from `index` on, values are filled in on success
+    /// `failed` holds the positions already known not to match, whether the rest matches
+    /// only depends on the position, so each one is tried once
+    fn match_parts(
+        &self,
+        index: usize,
+        input: &str,
+        values: &mut HashMap<String, String>,
+        failed: &mut Failed,
+    ) -> bool {
+        let position = index * failed.stride + input.len();
+        if failed.positions[position] {
+            return false;
+        }
+        let matched = self.match_part(index, input, values, failed);
+        failed.positions[position] = !matched;
+        matched
+    }
+
+    fn match_part(
+        &self,
+        index: usize,
+        input: &str,
+        values: &mut HashMap<String, String>,
+        failed: &mut Failed,
+    ) -> bool {
+        let Some(part) = self.parts.get(index) else {
+            return input.is_empty();
+        };
+        let (operator, variables) = match part {
+            Part::Literal(literal) => {
+                return input
+                    .strip_prefix(literal.as_str())
+                    .is_some_and(|input| self.match_parts(index + 1, input, values, failed));
+            }
+            Part::Expression {
+                operator,
+                variables,
+            } => (*operator, variables),
+        };
+        // an expression with a prefix may expand to nothing at all
+        let (body, optional) = match operator.prefix() {
+            Some(prefix) => match input.strip_prefix(prefix) {
+                Some(body) => (body, true),
+                None => return self.match_parts(index + 1, input, values, failed),
+            },
+            None => (input, false),
+        };
+        let exploded = variables.last().is_some_and(|var| var.explode);
+        // prefer the shortest expansion so later variables get their share
+        let mut items = Items::new(operator.separator());
+        let mut chars = body.chars().take_while(|c| operator.accepts(*c));
+        let mut end = 0;
+        loop {
+            // each candidate binds into its own map, so a failed one leaves nothing behind
+            let mut candidate = HashMap::new();
+            if (end > 0 || optional)
+                && items.fit(operator, variables)
+                && self.match_parts(index + 1, &body[end..], &mut candidate, failed)
+                && Self::assign(operator, variables, &body[..end], &mut candidate)
+            {
+                values.extend(candidate);
+                return true;
+            }
+            // more text only adds items, which already are too many
+            if !operator.named() && !exploded && items.separators >= variables.len() {
+                break;
+            }
+            let Some(c) = chars.next() else {
+                break;
+            };
+            items.push(c);
+            end += c.len_utf8();
+        }
+        // the prefix char may also belong to what follows
+        optional && self.match_parts(index + 1, input, values, failed)
+    }
+
+    /// Split the text an expression expanded to into the values of its variables
+    fn assign(
+        operator: Operator,
+        variables: &[VarSpec],
+        text: &str,
+        values: &mut HashMap<String, String>,
+    ) -> bool {
+        let separator = operator.separator();
+        if operator.named() {
+            for pair in text.split(separator) {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                if variables.iter().any(|var| var.name == name) {
+                    values.insert(name.to_string(), decode(value));
+                }
+            }
+            return true;
+        }
+        let mut items = text.split(separator);
+        for (i, var) in variables.iter().enumerate() {
+            let value = if var.explode && i == variables.len() - 1 {
+                let remaining: Vec<&str> = items.by_ref().collect();
+                if remaining.is_empty() {
+                    break;
+                }
+                remaining.join(&separator.to_string())
+            } else {
+                match items.next() {
+                    Some(item) => item.to_string(),
+                    None => break,
+                }
+            };
+            if value.is_empty() && operator.prefix().is_none() {
+                return false;
+            }
+            values.insert(var.name.clone(), decode(&value));
+        }
+        // every item has to belong to a variable
+        items.next().is_none()
+    }
+}
+
+/// Positions of a match known to fail, by part index and length of the remaining input
+struct Failed {
+    positions: Vec<bool>,
+    stride: usize,
+}
+
+/// How the text of an expression splits into items, tracked one char at a time
+/// so candidate expansions can be checked without splitting them again
+struct Items {
+    separator: char,
+    separators: usize,
+    /// Index of the first finished item that is empty
+    first_empty: Option<usize>,
+    /// Length of the item being read
+    current: usize,
+}
+
+impl Items {
+    fn new(separator: char) -> Self {
+        Self {
+            separator,
+            separators: 0,
+            first_empty: None,
+            current: 0,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if c == self.separator {
+            if self.current == 0 {
+                self.first_empty.get_or_insert(self.separators);
+            }
+            self.separators += 1;
+            self.current = 0;
+        } else {
+            self.current += 1;
+        }
+    }
+
+    /// Whether `UriTemplate::assign` accepts the text read so far
+    fn fit(&self, operator: Operator, variables: &[VarSpec]) -> bool {
+        if operator.named() {
+            return true;
+        }
+        let count = self.separators + 1;
+        let exploded = variables.last().is_some_and(|var| var.explode);
+        if !exploded && count > variables.len() {
+            return false;
+        }
+        if operator.prefix().is_some() {
+            return true;
+        }
+        // items taken by a single variable must not be empty
+        let single = if exploded {
+            variables.len() - 1
+        } else {
+            variables.len()
+        };
+        let empty = self
+            .first_empty
+            .or((self.current == 0).then_some(count - 1));
+        if empty.is_some_and(|index| index < single.min(count)) {
+            return false;
+        }
+        // neither may the value of an exploded variable
+        !(exploded && count == variables.len() && self.current == 0)
+    }
+}
+
+impl fmt::Display for UriTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl std::str::FromStr for UriTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn encode(value: &str, allow_reserved: bool) -> String {
+    const RESERVED: &str = ":/?#[]@!$&'()*+,;=";
+    let mut encoded = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric()
+            || matches!(c, '-' | '.' | '_' | '~')
+            || (allow_reserved && RESERVED.contains(c))
+        {
+            encoded.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    encoded
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Deserialize the variables matched from a uri into a typed value
+/// numbers and booleans are parsed from their text, lists are comma separated
+pub fn from_variables<P: de::DeserializeOwned>(variables: HashMap<String, String>) -> Result<P> {
+    let deserializer = MapDeserializer::<_, de::value::Error>::new(
+        variables
+            .into_iter()
+            .map(|(name, value)| (name, Variable(value))),
+    );
+    Ok(P::deserialize(deserializer)?)
+}
+
+/// The text value of a single variable
+struct Variable(String);
+
+impl<'de> IntoDeserializer<'de, de::value::Error> for Variable {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.0.parse().map_err(|_| {
+                    de::Error::custom(format!("invalid value '{}' for {}", self.0, stringify!($visit)))
+                })?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Variable {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let items: Vec<Variable> = self
+            .0
+            .split(',')
+            .map(|item| Variable(item.to_string()))
+            .collect();
+        visitor.visit_seq(de::value::SeqDeserializer::new(items.into_iter()))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn matches(template: &str, uri: &str) -> Option<Vec<(String, String)>> {
+        let values = UriTemplate::parse(template).unwrap().matches(uri)?;
+        let mut values: Vec<_> = values.into_iter().collect();
+        values.sort();
+        Some(values)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let template = UriTemplate::parse("db://{table}/{id}{?fields,limit}").unwrap();
+        assert_eq!(
+            template.variables().collect::<Vec<_>>(),
+            vec!["table", "id", "fields", "limit"]
+        );
+        assert!(UriTemplate::parse("db://{table").is_err());
+        assert!(UriTemplate::parse("db://table}").is_err());
+        assert!(UriTemplate::parse("db://{}").is_err());
+    }
+
+    #[test]
+    fn test_match_simple() {
+        let template = "db://{table}/{id}";
+        assert_eq!(
+            matches(template, "db://users/42"),
+            pairs(&[("id", "42"), ("table", "users")])
+        );
+        assert_eq!(matches(template, "db://users/"), None);
+        assert_eq!(matches(template, "db://users/42/extra"), None);
+        assert_eq!(
+            matches("file:///{name}.json", "file:///my%20data.json"),
+            pairs(&[("name", "my data")])
+        );
+    }
+
+    #[test]
+    fn test_match_operators() {
+        assert_eq!(
+            matches("file:///{+path}", "file:///src/lib.rs"),
+            pairs(&[("path", "src/lib.rs")])
+        );
+        assert_eq!(
+            matches("repo://{owner}{/path*}", "repo://me/src/main.rs"),
+            pairs(&[("owner", "me"), ("path", "src/main.rs")])
+        );
+        assert_eq!(
+            matches(
+                "db://{table}{?limit,offset}",
+                "db://users?offset=10&limit=5"
+            ),
+            pairs(&[("limit", "5"), ("offset", "10"), ("table", "users")])
+        );
+        assert_eq!(
+            matches("db://{table}{?limit,offset}", "db://users"),
+            pairs(&[("table", "users")])
+        );
+        assert_eq!(
+            matches("doc://{name}{#section}", "doc://guide#install"),
+            pairs(&[("name", "guide"), ("section", "install")])
+        );
+        assert_eq!(
+            matches("img://{name}{.format}", "img://logo.png"),
+            pairs(&[("format", "png"), ("name", "logo")])
+        );
+        assert_eq!(
+            matches("geo://{lat,lon}", "geo://1.5,-2"),
+            pairs(&[("lat", "1.5"), ("lon", "-2")])
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let template = UriTemplate::parse("db://{table}/{id}{?fields}").unwrap();
+        let values = HashMap::from([
+            ("table".to_string(), "my users".to_string()),
+            ("id".to_string(), "7".to_string()),
+        ]);
+        let uri = template.expand(&values);
+        assert_eq!(uri, "db://my%20users/7");
+        assert_eq!(template.matches(&uri).unwrap(), values);
+    }
+
+    #[test]
+    fn test_expand_prefix() {
+        let template = UriTemplate::parse("notes://{name:3}/{+path:6}").unwrap();
+        let values = HashMap::from([
+            ("name".to_string(), "résumé".to_string()),
+            ("path".to_string(), "a/b c/d".to_string()),
+        ]);
+        assert_eq!(template.expand(&values), "notes://r%C3%A9s/a/b%20c/");
+        assert!(UriTemplate::parse("notes://{name:0}").is_err());
+        assert!(UriTemplate::parse("notes://{name:x}").is_err());
+    }
+
+    #[test]
+    fn test_match_adjacent_expressions() {
+        let template = UriTemplate::parse("x://{a}{b}{c}{d}{e}{f}!").unwrap();
+        let uri = format!("x://{}", "a".repeat(500));
+        assert!(template.matches(&uri).is_none());
+        let matched = template.matches(&format!("{uri}!")).unwrap();
+        assert_eq!(matched.len(), 6);
+        assert_eq!(matched["a"], "a");
+        assert_eq!(matched["f"].len(), 495);
+        // only the bindings of the expansion that matched are returned
+        assert_eq!(
+            matches("x://{a}{/b,c}{?d}", "x://p/q/r"),
+            pairs(&[("a", "p"), ("b", "q"), ("c", "r")])
+        );
+        let long = format!("x://{}!", "a".repeat(MAX_MATCH_LENGTH));
+        assert!(template.matches(&long).is_none());
+    }
+
+    #[test]
+    fn test_from_variables() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Params {
+            table: String,
+            id: u64,
+            verbose: Option<bool>,
+            fields: Option<Vec<String>>,
+        }
+
+        let params: Params = from_variables(HashMap::from([
+            ("table".to_string(), "users".to_string()),
+            ("id".to_string(), "42".to_string()),
+            ("fields".to_string(), "name,email".to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            params,
+            Params {
+                table: "users".to_string(),
+                id: 42,
+                verbose: None,
+                fields: Some(vec!["name".to_string(), "email".to_string()]),
+            }
+        );
+
+        let invalid = from_variables::<Params>(HashMap::from([
+            ("table".to_string(), "users".to_string()),
+            ("id".to_string(), "abc".to_string()),
+        ]));
+        assert!(invalid.is_err());
+    }
+}
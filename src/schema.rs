@@ -0,0 +1,374 @@
+//! Validation of json values against the subset of JSON Schema draft 2020-12 tools use:
+//! `type`, `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`,
+//! `prefixItems`, length, size and range bounds, `allOf`/`anyOf`/`oneOf`/`not` and local `$ref`s.
+//! Unknown keywords are ignored.
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A place where a value does not match its schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON pointer to the offending value, empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Every violation of `schema` by `value`, empty when the value is valid
+pub fn validate(schema: &Value, value: &Value) -> Vec<Violation> {
+    let mut validator = Validator {
+        root: schema,
+        violations: Vec::new(),
+        following: Vec::new(),
+    };
+    validator.validate(schema, value, String::new());
+    validator.violations
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    violations: Vec<Violation>,
+    /// `$ref`s being followed and the path of the value they are followed for
+    /// following one again for the same value would never end
+    following: Vec<(&'a str, String)>,
+}
+
+impl<'a> Validator<'a> {
+    fn fail(&mut self, path: &str, message: String) {
+        self.violations.push(Violation {
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn is_valid(&self, schema: &'a Value, value: &Value, path: &str) -> bool {
+        let mut validator = Validator {
+            root: self.root,
+            violations: Vec::new(),
+            following: self.following.clone(),
+        };
+        validator.validate(schema, value, path.to_string());
+        validator.violations.is_empty()
+    }
+
+    fn validate(&mut self, schema: &'a Value, value: &Value, path: String) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.fail(&path, "no value is allowed here".to_string()),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            let following = (reference.as_str(), path.clone());
+            if self.following.contains(&following) {
+                return self.fail(&path, format!("circular $ref {reference}"));
+            }
+            match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+            {
+                Some(target) => {
+                    self.following.push(following);
+                    self.validate(target, value, path.clone());
+                    self.following.pop();
+                }
+                None => self.fail(&path, format!("unresolvable $ref {reference}")),
+            }
+        }
+
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                Value::String(name) => vec![name.as_str()],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+                return self.fail(
+                    &path,
+                    format!(
+                        "expected {}, got {}",
+                        allowed.join(" or "),
+                        type_name(value)
+                    ),
+                );
+            }
+        }
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(value) {
+                self.fail(
+                    &path,
+                    format!("must be one of {}", Value::from(options.clone())),
+                );
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                self.fail(&path, format!("must be {expected}"));
+            }
+        }
+
+        match value {
+            Value::Object(object) => self.validate_object(schema, object, &path),
+            Value::Array(items) => self.validate_array(schema, items, &path),
+            Value::String(string) => {
+                let length = string.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                    if length < min {
+                        self.fail(&path, format!("must be at least {min} characters long"));
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                    if length > max {
+                        self.fail(&path, format!("must be at most {max} characters long"));
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+                if let Some(min) = bound("minimum").filter(|min| number < *min) {
+                    self.fail(&path, format!("must be >= {min}"));
+                }
+                if let Some(max) = bound("maximum").filter(|max| number > *max) {
+                    self.fail(&path, format!("must be <= {max}"));
+                }
+                if let Some(min) = bound("exclusiveMinimum").filter(|min| number <= *min) {
+                    self.fail(&path, format!("must be > {min}"));
+                }
+                if let Some(max) = bound("exclusiveMaximum").filter(|max| number >= *max) {
+                    self.fail(&path, format!("must be < {max}"));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                self.validate(schema, value, path.clone());
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+            if !schemas
+                .iter()
+                .any(|schema| self.is_valid(schema, value, &path))
+            {
+                self.fail(
+                    &path,
+                    "does not match any of the allowed schemas".to_string(),
+                );
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+            let matching = schemas
+                .iter()
+                .filter(|schema| self.is_valid(schema, value, &path))
+                .count();
+            if matching != 1 {
+                self.fail(
+                    &path,
+                    format!("must match exactly one schema, matches {matching}"),
+                );
+            }
+        }
+        if let Some(schema) = schema.get("not") {
+            if self.is_valid(schema, value, &path) {
+                self.fail(&path, "matches a schema it must not match".to_string());
+            }
+        }
+    }
+
+    fn validate_object(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        object: &Map<String, Value>,
+        path: &str,
+    ) {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.fail(path, format!("missing required property '{name}'"));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, value) in object {
+            let property_path = format!("{path}/{}", escape(name));
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => self.validate(property, value, property_path),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        self.fail(path, format!("unexpected property '{name}'"))
+                    }
+                    Some(additional) => self.validate(additional, value, property_path),
+                    None => {}
+                },
+            }
+        }
+        let size = object.len() as u64;
+        if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+            if size < min {
+                self.fail(path, format!("must have at least {min} properties"));
+            }
+        }
+        if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+            if size > max {
+                self.fail(path, format!("must have at most {max} properties"));
+            }
+        }
+    }
+
+    fn validate_array(&mut self, schema: &'a Map<String, Value>, items: &[Value], path: &str) {
+        let prefix = match schema.get("prefixItems") {
+            Some(Value::Array(prefix)) => prefix.as_slice(),
+            _ => &[],
+        };
+        for (index, item) in items.iter().enumerate() {
+            let item_path = format!("{path}/{index}");
+            if let Some(item_schema) = prefix.get(index).or_else(|| schema.get("items")) {
+                self.validate(item_schema, item, item_path);
+            }
+        }
+        let size = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            if size < min {
+                self.fail(path, format!("must have at least {min} items"));
+            }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            if size > max {
+                self.fail(path, format!("must have at most {max} items"));
+            }
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let duplicate = items
+                .iter()
+                .enumerate()
+                .any(|(index, item)| items[..index].contains(item));
+            if duplicate {
+                self.fail(path, "items must be unique".to_string());
+            }
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => match value {
+            Value::Number(number) => {
+                number.is_i64()
+                    || number.is_u64()
+                    || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            _ => false,
+        },
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Escape a property name for use in a JSON pointer
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn messages(schema: Value, value: Value) -> Vec<String> {
+        validate(&schema, &value)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "query": {"type": "string", "minLength": 1},
+                "limit": {"type": ["integer", "null"], "minimum": 1},
+                "tags": {"type": "array", "items": {"$ref": "#/$defs/Tag"}}
+            },
+            "required": ["query"],
+            "additionalProperties": false,
+            "$defs": {
+                "Tag": {"enum": ["a", "b"]}
+            }
+        });
+        assert!(messages(schema.clone(), json!({"query": "x", "limit": null})).is_empty());
+        assert_eq!(
+            messages(
+                schema,
+                json!({"limit": 0.5, "tags": ["a", "c"], "extra": true})
+            ),
+            vec![
+                "/: missing required property 'query'",
+                "/: unexpected property 'extra'",
+                "/limit: expected integer or null, got number",
+                "/tags/1: must be one of [\"a\",\"b\"]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_circular_ref() {
+        let schema = json!({"$defs": {"A": {"$ref": "#/$defs/A"}}, "$ref": "#/$defs/A"});
+        assert_eq!(
+            messages(schema, json!(1)),
+            vec!["/: circular $ref #/$defs/A"]
+        );
+        let schema = json!({"$defs": {"A": {"not": {"$ref": "#/$defs/A"}}}, "$ref": "#/$defs/A"});
+        // through a combinator the cycle only has to end, the schema is contradictory anyway
+        messages(schema, json!(1));
+        // recursive schemas are fine as long as each level validates a nested value
+        let schema = json!({
+            "$defs": {"Node": {"type": "object", "properties": {"next": {"$ref": "#/$defs/Node"}}}},
+            "$ref": "#/$defs/Node"
+        });
+        assert!(messages(schema.clone(), json!({"next": {"next": {}}})).is_empty());
+        assert_eq!(
+            messages(schema, json!({"next": {"next": 1}})),
+            vec!["/next/next: expected object, got number"]
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let schema = json!({"oneOf": [{"type": "integer"}, {"type": "number"}]});
+        assert!(messages(schema.clone(), json!(1.5)).is_empty());
+        assert_eq!(
+            messages(schema, json!(1)),
+            vec!["/: must match exactly one schema, matches 2"]
+        );
+        assert!(messages(json!({"anyOf": [{"type": "string"}]}), json!("x")).is_empty());
+        assert_eq!(
+            messages(json!(false), json!(1)),
+            vec!["/: no value is allowed here"]
+        );
+    }
+}
@@ -1,39 +1,96 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use crate::{
-    tools::Tools,
-    types::{CallToolRequest, ListRequest, ToolsListResponse},
+    pagination::paginate,
+    prompts::Prompts,
+    registry::{PromptsHandle, RegistryHandle, ResourcesHandle, Shared, ToolsHandle},
+    resources::{Resources, UpdateSink},
+    tools::{ToolContext, Tools},
+    transport::JsonRpcRequest,
+    types::{
+        CallToolRequest, ErrorCode, GetPromptRequest, ListRequest, PromptsListResponse,
+        ProtocolError, ReadResourceRequest, ResourceTemplatesListResponse,
+        ResourceUpdatedNotification, ResourcesListResponse, SubscribeRequest, ToolsListResponse,
+        UnsubscribeRequest,
+    },
 };
 
 use super::{
-    protocol::{Protocol, ProtocolBuilder},
+    protocol::{Protocol, ProtocolBuilder, RequestContext},
     transport::Transport,
     types::{
-        ClientCapabilities, Implementation, InitializeRequest, InitializeResponse,
-        ServerCapabilities, LATEST_PROTOCOL_VERSION,
+        ClientCapabilities, Implementation, InitializeRequest, InitializeResponse, ProtocolVersion,
+        ServerCapabilities, SUPPORTED_PROTOCOL_VERSIONS,
     },
 };
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Serialize};
+use url::Url;
+
+/// Methods a client may call before the session is initialized
+const PRE_INIT_METHODS: &[&str] = &["initialize", "ping"];
+
+/// Phase of the MCP session lifecycle
+/// https://spec.modelcontextprotocol.io/specification/basic/lifecycle/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecyclePhase {
+    /// Waiting for the `initialize` request
+    Uninitialized,
+    /// `initialize` was answered, waiting for `notifications/initialized`
+    /// requests are already served in this phase
+    Initializing,
+    /// The session is ready for normal operation
+    Initialized,
+    /// The transport closed and the server stopped listening
+    Shutdown,
+}
 
 #[derive(Clone)]
 pub struct ServerState {
     client_capabilities: Option<ClientCapabilities>,
     client_info: Option<Implementation>,
-    initialized: bool,
+    protocol_version: Option<ProtocolVersion>,
+    phase: LifecyclePhase,
+    /// Resources the client subscribed to in this session
+    subscriptions: HashSet<Url>,
+}
+
+type InitializeHook = dyn Fn(&InitializeRequest) -> Result<()> + Send + Sync;
+type LifecycleHook = dyn Fn() + Send + Sync;
+
+/// User callbacks invoked on lifecycle transitions
+#[derive(Default, Clone)]
+struct LifecycleHooks {
+    on_initialize: Option<Arc<InitializeHook>>,
+    on_initialized: Option<Arc<LifecycleHook>>,
+    on_shutdown: Option<Arc<LifecycleHook>>,
 }
 
 #[derive(Clone)]
 pub struct Server<T: Transport> {
     protocol: Protocol<T>,
     state: Arc<RwLock<ServerState>>,
+    on_shutdown: Option<Arc<LifecycleHook>>,
+    tools: Option<ToolsHandle>,
+    prompts: Option<PromptsHandle>,
+    resources: Option<ResourcesHandle>,
 }
 
 pub struct ServerBuilder<T: Transport> {
     protocol: ProtocolBuilder<T>,
     server_info: Implementation,
     capabilities: ServerCapabilities,
+    protocol_versions: Vec<ProtocolVersion>,
     tools: Option<Tools>,
+    prompts: Option<Prompts>,
+    resources: Option<Resources>,
+    page_size: Option<usize>,
+    hooks: LifecycleHooks,
 }
 
 impl<T: Transport> ServerBuilder<T> {
@@ -52,6 +109,16 @@ impl<T: Transport> ServerBuilder<T> {
         self
     }
 
+    /// Protocol versions the server accepts, preferred first
+    /// a client requesting another version is answered with the preferred one
+    pub fn protocol_versions(
+        mut self,
+        versions: impl IntoIterator<Item = ProtocolVersion>,
+    ) -> Self {
+        self.protocol_versions = versions.into_iter().collect();
+        self
+    }
+
     /// Register a typed request handler
     /// for higher-level api use add tool
     pub fn request_handler<Req, Resp>(
@@ -67,6 +134,38 @@ impl<T: Transport> ServerBuilder<T> {
         self
     }
 
+    /// Register a typed request handler that awaits its response
+    /// it runs on its own task, so a slow handler does not hold up other messages
+    pub fn async_request_handler<Req, Resp, Fut>(
+        mut self,
+        method: &str,
+        handler: impl Fn(Req) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Req: DeserializeOwned + Send + Sync + 'static,
+        Resp: Serialize + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp>> + Send + 'static,
+    {
+        self.protocol = self.protocol.async_request_handler(method, handler);
+        self
+    }
+
+    /// Register an async request handler that also gets the request id, `_meta`,
+    /// a cancellation token and the client to send requests back to
+    pub fn request_handler_with_context<Req, Resp, Fut>(
+        mut self,
+        method: &str,
+        handler: impl Fn(Req, RequestContext) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Req: DeserializeOwned + Send + Sync + 'static,
+        Resp: Serialize + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp>> + Send + 'static,
+    {
+        self.protocol = self.protocol.request_handler_with_context(method, handler);
+        self
+    }
+
     pub fn notification_handler<N>(
         mut self,
         method: &str,
@@ -84,6 +183,52 @@ impl<T: Transport> ServerBuilder<T> {
         self
     }
 
+    pub fn prompts(mut self, prompts: Prompts) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = Some(resources);
+        self
+    }
+
+    /// How long requests still running when the transport closes may take to finish
+    /// before they are cancelled, five seconds by default
+    pub fn shutdown_grace(mut self, shutdown_grace: Duration) -> Self {
+        self.protocol = self.protocol.shutdown_grace(shutdown_grace);
+        self
+    }
+
+    /// Maximum number of items per page of tools, prompts, resources and templates
+    /// by default every list is returned in a single page
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Called with the client's `initialize` request before it is accepted
+    /// returning an error rejects the initialization
+    pub fn on_initialize(
+        mut self,
+        hook: impl Fn(&InitializeRequest) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_initialize = Some(Arc::new(hook));
+        self
+    }
+
+    /// Called once the client confirms initialization with `notifications/initialized`
+    pub fn on_initialized(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.hooks.on_initialized = Some(Arc::new(hook));
+        self
+    }
+
+    /// Called when the server stops listening, usually because the transport closed
+    pub fn on_shutdown(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.hooks.on_shutdown = Some(Arc::new(hook));
+        self
+    }
+
     pub fn build(self) -> Server<T> {
         Server::new(self)
     }
@@ -98,7 +243,12 @@ impl<T: Transport> Server<T> {
                 version: env!("CARGO_PKG_VERSION").to_string(),
             },
             capabilities: Default::default(),
+            protocol_versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
             tools: None,
+            prompts: None,
+            resources: None,
+            page_size: None,
+            hooks: Default::default(),
         }
     }
 
@@ -106,41 +256,252 @@ impl<T: Transport> Server<T> {
         let state = Arc::new(RwLock::new(ServerState {
             client_capabilities: None,
             client_info: None,
-            initialized: false,
+            protocol_version: None,
+            phase: LifecyclePhase::Uninitialized,
+            subscriptions: HashSet::new(),
         }));
 
-        // Initialize protocol with handlers
-        let mut protocol = builder
-            .protocol
-            .request_handler(
-                "initialize",
-                Self::handle_init(state.clone(), builder.server_info, builder.capabilities),
-            )
-            .notification_handler(
-                "notifications/initialized",
-                Self::handle_initialized(state.clone()),
-            );
-        if let Some(tools) = builder.tools {
+        let mut protocol = builder.protocol;
+        let page_size = builder.page_size;
+        let resource_updates = builder.resources.as_ref().map(Resources::updates);
+        let tools = builder.tools.map(Shared::new);
+        let prompts = builder.prompts.map(Shared::new);
+        let resources = builder.resources.map(Shared::new);
+        if let Some(tools) = &tools {
             // Add tools handlers if not already present
-            let tools = Arc::new(tools);
-            let tools_clone = tools.clone();
+            let tools_list = tools.clone();
+            let tools_call = tools.clone();
+            let tools_state = state.clone();
             protocol = protocol
-                .request_handler("tools/list", move |_req: ListRequest| {
+                .request_handler("tools/list", move |req: ListRequest| {
+                    let tools = tools_list.read()?.list_tools();
+                    let (tools, next_cursor) = paginate(tools, req.cursor.as_deref(), page_size)?;
                     Ok(ToolsListResponse {
-                        tools: tools.list_tools(),
-                        next_cursor: None,
+                        tools,
+                        next_cursor,
+                        meta: None,
+                    })
+                })
+                .request_handler_with_context(
+                    "tools/call",
+                    move |req: CallToolRequest, request: RequestContext| {
+                        let tools = tools_call.clone();
+                        let state = tools_state.clone();
+                        async move {
+                            let context = {
+                                let state = read(&state)?;
+                                ToolContext {
+                                    request,
+                                    client_info: state.client_info.clone(),
+                                    client_capabilities: state.client_capabilities.clone(),
+                                    protocol_version: state.protocol_version,
+                                }
+                            };
+                            // the lock is released before the tool runs
+                            let call = tools.read()?.call_tool_with_context(req, context);
+                            Ok(call.await)
+                        }
+                    },
+                );
+        }
+        if let Some(prompts) = &prompts {
+            let prompts_list = prompts.clone();
+            let prompts_get = prompts.clone();
+            protocol = protocol
+                .request_handler("prompts/list", move |req: ListRequest| {
+                    let prompts = prompts_list.read()?.list_prompts();
+                    let (prompts, next_cursor) =
+                        paginate(prompts, req.cursor.as_deref(), page_size)?;
+                    Ok(PromptsListResponse {
+                        prompts,
+                        next_cursor,
+                        meta: None,
+                    })
+                })
+                .request_handler("prompts/get", move |req: GetPromptRequest| {
+                    prompts_get.read()?.get_prompt(req)
+                });
+        }
+        if let Some(resources) = &resources {
+            let resources_list = resources.clone();
+            let resources_read = resources.clone();
+            let resources_templates = resources.clone();
+            let resources_subscribe = resources.clone();
+            let state_subscribe = state.clone();
+            let state_unsubscribe = state.clone();
+            protocol = protocol
+                .request_handler("resources/list", move |req: ListRequest| {
+                    let resources = resources_list.read()?.list_resources();
+                    let (resources, next_cursor) =
+                        paginate(resources, req.cursor.as_deref(), page_size)?;
+                    Ok(ResourcesListResponse {
+                        resources,
+                        next_cursor,
                         meta: None,
                     })
                 })
-                .request_handler("tools/call", move |req: CallToolRequest| {
-                    let response = tools_clone.call_tool(req);
-                    Ok(response)
+                .request_handler("resources/read", move |req: ReadResourceRequest| {
+                    resources_read.read()?.read_resource(req)
+                })
+                .request_handler("resources/templates/list", move |req: ListRequest| {
+                    let templates = resources_templates.read()?.list_templates();
+                    let (resource_templates, next_cursor) =
+                        paginate(templates, req.cursor.as_deref(), page_size)?;
+                    Ok(ResourceTemplatesListResponse {
+                        resource_templates,
+                        next_cursor,
+                        meta: None,
+                    })
+                })
+                .request_handler("resources/subscribe", move |req: SubscribeRequest| {
+                    if !resources_subscribe.read()?.contains(&req.uri) {
+                        return Err(ProtocolError::new(
+                            ErrorCode::ResourceNotFound,
+                            format!("Resource {} not found", req.uri),
+                        )
+                        .into());
+                    }
+                    write(&state_subscribe)?.subscriptions.insert(req.uri);
+                    Ok(serde_json::json!({}))
+                })
+                .request_handler("resources/unsubscribe", move |req: UnsubscribeRequest| {
+                    write(&state_unsubscribe)?.subscriptions.remove(&req.uri);
+                    Ok(serde_json::json!({}))
                 });
         }
 
+        // Advertise whatever the registered handlers can serve
+        let mut capabilities = Self::derive_capabilities(builder.capabilities, &protocol);
+        // Registries can change at runtime through their handles
+        if tools.is_some() {
+            let tools = capabilities.tools.get_or_insert_with(Default::default);
+            tools.list_changed.get_or_insert(true);
+        }
+        if prompts.is_some() {
+            let prompts = capabilities.prompts.get_or_insert_with(Default::default);
+            prompts.list_changed.get_or_insert(true);
+        }
+        if resources.is_some() {
+            let resources = capabilities.resources.get_or_insert_with(Default::default);
+            resources.list_changed.get_or_insert(true);
+        }
+
+        // Initialize protocol with handlers
+        let protocol = protocol
+            .request_guard(Self::guard_lifecycle(state.clone()))
+            .request_handler(
+                "initialize",
+                Self::handle_init(
+                    state.clone(),
+                    builder.server_info,
+                    capabilities,
+                    builder.protocol_versions,
+                    builder.hooks.on_initialize,
+                ),
+            )
+            .notification_handler(
+                "notifications/initialized",
+                Self::handle_initialized(state.clone(), builder.hooks.on_initialized),
+            )
+            .request_handler("ping", |_: serde_json::Value| Ok(serde_json::json!({})));
+
+        let protocol = protocol.build();
+        if let Some(updates) = resource_updates {
+            updates.connect(SessionUpdates {
+                protocol: protocol.clone(),
+                state: state.clone(),
+            });
+        }
+
+        let handle = |method: &'static str| {
+            Self::notify_list_changed(protocol.clone(), state.clone(), method)
+        };
+        let tools = tools
+            .map(|tools| RegistryHandle::new(tools, handle("notifications/tools/list_changed")));
+        let prompts = prompts.map(|prompts| {
+            RegistryHandle::new(prompts, handle("notifications/prompts/list_changed"))
+        });
+        let resources = resources.map(|resources| {
+            RegistryHandle::new(resources, handle("notifications/resources/list_changed"))
+        });
+
         Server {
-            protocol: protocol.build(),
+            protocol,
             state,
+            on_shutdown: builder.hooks.on_shutdown,
+            tools,
+            prompts,
+            resources,
+        }
+    }
+
+    // Sends `method` once the session is initialized, earlier changes are
+    // already part of the lists the client fetches after initializing
+    fn notify_list_changed(
+        protocol: Protocol<T>,
+        state: Arc<RwLock<ServerState>>,
+        method: &'static str,
+    ) -> impl Fn() -> Result<()> + Send + Sync + 'static {
+        move || {
+            let phase = state
+                .read()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?
+                .phase;
+            if phase != LifecyclePhase::Initialized {
+                return Ok(());
+            }
+            protocol.notify(method, None)
+        }
+    }
+
+    // Fills in the capabilities of every subsystem that has handlers registered
+    // explicitly configured capabilities are kept as they are
+    fn derive_capabilities(
+        mut capabilities: ServerCapabilities,
+        protocol: &ProtocolBuilder<T>,
+    ) -> ServerCapabilities {
+        if protocol.has_request_handler("tools/list") {
+            capabilities.tools.get_or_insert_with(Default::default);
+        }
+        if protocol.has_request_handler("prompts/list") {
+            capabilities.prompts.get_or_insert_with(Default::default);
+        }
+        if protocol.has_request_handler("resources/list") {
+            let resources = capabilities.resources.get_or_insert_with(Default::default);
+            if protocol.has_request_handler("resources/subscribe") {
+                resources.subscribe.get_or_insert(true);
+            }
+        }
+        if protocol.has_request_handler("logging/setLevel") {
+            capabilities.logging.get_or_insert_with(Default::default);
+        }
+        if protocol.has_request_handler("completion/complete") {
+            capabilities
+                .completions
+                .get_or_insert_with(Default::default);
+        }
+        capabilities
+    }
+
+    // Rejects every request but `initialize` and `ping` until `initialize` was answered
+    // requests arriving before `notifications/initialized` are served on purpose, the spec
+    // only says clients should not send them and rejecting them would break lenient clients
+    fn guard_lifecycle(state: Arc<RwLock<ServerState>>) -> impl Fn(&JsonRpcRequest) -> Result<()> {
+        move |req| {
+            let state = state.read().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            if state.phase == LifecyclePhase::Uninitialized
+                && !PRE_INIT_METHODS.contains(&req.method.as_str())
+            {
+                return Err(ProtocolError::new(
+                    ErrorCode::InvalidRequest,
+                    format!(
+                        "Server not initialized: received '{}' before 'initialize'",
+                        req.method
+                    ),
+                )
+                .into());
+            }
+            Ok(())
         }
     }
 
@@ -149,16 +510,40 @@ impl<T: Transport> Server<T> {
         state: Arc<RwLock<ServerState>>,
         server_info: Implementation,
         capabilities: ServerCapabilities,
+        protocol_versions: Vec<ProtocolVersion>,
+        on_initialize: Option<Arc<InitializeHook>>,
     ) -> impl Fn(InitializeRequest) -> Result<InitializeResponse> {
+        let already_initialized = || -> Result<InitializeResponse> {
+            Err(ProtocolError::new(ErrorCode::InvalidRequest, "Server already initialized").into())
+        };
         move |req| {
+            let phase = state
+                .read()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?
+                .phase;
+            if phase != LifecyclePhase::Uninitialized {
+                return already_initialized();
+            }
+            // the hook runs without holding the state lock, so it may use the server freely
+            if let Some(hook) = &on_initialize {
+                hook(&req)?;
+            }
+            let protocol_version =
+                ProtocolVersion::negotiate(&req.protocol_version, &protocol_versions)
+                    .ok_or_else(|| anyhow::anyhow!("No protocol versions configured"))?;
             let mut state = state
                 .write()
                 .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            if state.phase != LifecyclePhase::Uninitialized {
+                return already_initialized();
+            }
             state.client_capabilities = Some(req.capabilities);
             state.client_info = Some(req.client_info);
+            state.protocol_version = Some(protocol_version);
+            state.phase = LifecyclePhase::Initializing;
 
             Ok(InitializeResponse {
-                protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+                protocol_version: protocol_version.to_string(),
                 capabilities: capabilities.clone(),
                 server_info: server_info.clone(),
             })
@@ -166,16 +551,42 @@ impl<T: Transport> Server<T> {
     }
 
     // Helper function for initialized handler
-    fn handle_initialized(state: Arc<RwLock<ServerState>>) -> impl Fn(()) -> Result<()> {
+    fn handle_initialized(
+        state: Arc<RwLock<ServerState>>,
+        on_initialized: Option<Arc<LifecycleHook>>,
+    ) -> impl Fn(()) -> Result<()> {
         move |_| {
-            let mut state = state
-                .write()
-                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
-            state.initialized = true;
+            {
+                let mut state = state
+                    .write()
+                    .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+                if state.phase != LifecyclePhase::Initializing {
+                    return Ok(());
+                }
+                state.phase = LifecyclePhase::Initialized;
+            }
+            if let Some(hook) = &on_initialized {
+                hook();
+            }
             Ok(())
         }
     }
 
+    /// Handle for changing the tools at runtime, `None` unless tools were registered
+    pub fn tools_handle(&self) -> Option<ToolsHandle> {
+        self.tools.clone()
+    }
+
+    /// Handle for changing the prompts at runtime, `None` unless prompts were registered
+    pub fn prompts_handle(&self) -> Option<PromptsHandle> {
+        self.prompts.clone()
+    }
+
+    /// Handle for changing the resources at runtime, `None` unless resources were registered
+    pub fn resources_handle(&self) -> Option<ResourcesHandle> {
+        self.resources.clone()
+    }
+
     pub fn get_client_capabilities(&self) -> Option<ClientCapabilities> {
         self.state.read().ok()?.client_capabilities.clone()
     }
@@ -184,15 +595,823 @@ impl<T: Transport> Server<T> {
         self.state.read().ok()?.client_info.clone()
     }
 
+    /// The protocol version agreed on during initialization
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.state.read().ok()?.protocol_version
+    }
+
     pub fn is_initialized(&self) -> bool {
+        self.phase() == LifecyclePhase::Initialized
+    }
+
+    pub fn phase(&self) -> LifecyclePhase {
         self.state
             .read()
-            .ok()
-            .map(|state| state.initialized)
-            .unwrap_or(false)
+            .map(|state| state.phase)
+            .unwrap_or(LifecyclePhase::Shutdown)
     }
 
+    /// Process messages until the transport closes, then run the shutdown hook
     pub async fn listen(&self) -> Result<()> {
-        self.protocol.listen().await
+        let result = self.protocol.listen().await;
+        if let Ok(mut state) = self.state.write() {
+            state.phase = LifecyclePhase::Shutdown;
+            state.subscriptions.clear();
+        }
+        if let Some(hook) = &self.on_shutdown {
+            hook();
+        }
+        result
+    }
+}
+
+fn read<R>(registry: &RwLock<R>) -> Result<std::sync::RwLockReadGuard<'_, R>> {
+    registry
+        .read()
+        .map_err(|_| anyhow::anyhow!("Lock poisoned"))
+}
+
+fn write<R>(registry: &RwLock<R>) -> Result<std::sync::RwLockWriteGuard<'_, R>> {
+    registry
+        .write()
+        .map_err(|_| anyhow::anyhow!("Lock poisoned"))
+}
+
+/// Publishes resource updates to the client of a session, if it subscribed
+struct SessionUpdates<T: Transport> {
+    protocol: Protocol<T>,
+    state: Arc<RwLock<ServerState>>,
+}
+
+impl<T: Transport> UpdateSink for SessionUpdates<T> {
+    fn is_subscribed(&self, uri: &Url) -> bool {
+        read(&self.state).is_ok_and(|state| state.subscriptions.contains(uri))
+    }
+
+    fn send(&self, uri: &Url) -> Result<()> {
+        let notification = ResourceUpdatedNotification { uri: uri.clone() };
+        self.protocol.notify(
+            "notifications/resources/updated",
+            Some(serde_json::to_value(notification)?),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::transport::{
+        JsonRpcMessage, JsonRpcNotification, JsonRpcResponse, Message, QueueTransport,
+    };
+    use crate::types::LATEST_PROTOCOL_VERSION;
+    use serde_json::json;
+
+    fn request(id: u64, method: &str, params: serde_json::Value) -> Message {
+        JsonRpcMessage::Request(JsonRpcRequest {
+            id,
+            method: method.to_string(),
+            params: Some(params),
+            ..Default::default()
+        })
+    }
+
+    fn initialized() -> Message {
+        JsonRpcMessage::Notification(JsonRpcNotification {
+            method: "notifications/initialized".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn initialize_params() -> serde_json::Value {
+        initialize_params_with_version(LATEST_PROTOCOL_VERSION)
+    }
+
+    fn initialize_params_with_version(version: &str) -> serde_json::Value {
+        json!({
+            "protocolVersion": version,
+            "capabilities": {},
+            "clientInfo": {"name": "test", "version": "0.1.0"}
+        })
+    }
+
+    /// Run the server over the given messages and return its responses
+    async fn run(
+        builder: impl FnOnce(ServerBuilder<QueueTransport>) -> ServerBuilder<QueueTransport>,
+        messages: Vec<Message>,
+    ) -> (Server<QueueTransport>, Vec<JsonRpcResponse>) {
+        let transport = QueueTransport::new(messages);
+        let outgoing = transport.outgoing.clone();
+        let server = builder(Server::builder(transport)).build();
+        assert!(server.listen().await.is_err());
+        let responses = outgoing
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|message| match message {
+                JsonRpcMessage::Response(response) => Some(response.clone()),
+                _ => None,
+            })
+            .collect();
+        (server, responses)
+    }
+
+    #[tokio::test]
+    async fn test_rejects_requests_before_initialize() {
+        let (_, responses) = run(
+            |builder| builder.tools(Tools::default()),
+            vec![
+                request(0, "tools/list", json!({})),
+                request(1, "ping", json!({})),
+            ],
+        )
+        .await;
+        let error = responses[0].error.as_ref().expect("should be rejected");
+        assert_eq!(error.code, ErrorCode::InvalidRequest as i32);
+        assert_eq!(responses[1].result, Some(json!({})));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_duplicate_initialize() {
+        let (server, responses) = run(
+            |builder| builder,
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "initialize", initialize_params()),
+                initialized(),
+            ],
+        )
+        .await;
+        assert!(responses[0].result.is_some());
+        let error = responses[1].error.as_ref().expect("should be rejected");
+        assert_eq!(error.code, ErrorCode::InvalidRequest as i32);
+        assert_eq!(server.phase(), LifecyclePhase::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (e1, e2, e3) = (events.clone(), events.clone(), events.clone());
+        let (_, responses) = run(
+            move |builder| {
+                builder
+                    .tools(Tools::default())
+                    .on_initialize(move |req| {
+                        e1.lock()
+                            .unwrap()
+                            .push(format!("initialize {}", req.client_info.name));
+                        Ok(())
+                    })
+                    .on_initialized(move || e2.lock().unwrap().push("initialized".to_string()))
+                    .on_shutdown(move || e3.lock().unwrap().push("shutdown".to_string()))
+            },
+            vec![
+                request(0, "initialize", initialize_params()),
+                initialized(),
+                request(1, "tools/list", json!({})),
+            ],
+        )
+        .await;
+        assert!(responses.iter().all(|response| response.error.is_none()));
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["initialize test", "initialized", "shutdown"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_on_initialize_can_use_the_server() {
+        let server = Arc::new(std::sync::OnceLock::<Arc<Server<QueueTransport>>>::new());
+        let hook_server = server.clone();
+        let transport = QueueTransport::new(vec![request(0, "initialize", initialize_params())]);
+        let outgoing = transport.outgoing.clone();
+        let built = Arc::new(
+            Server::builder(transport)
+                .on_initialize(move |_| {
+                    // would deadlock if the hook ran under the state lock
+                    assert!(hook_server.get().unwrap().get_client_info().is_none());
+                    Ok(())
+                })
+                .build(),
+        );
+        let _ = server.set(built.clone());
+        assert!(built.listen().await.is_err());
+        let JsonRpcMessage::Response(response) = &outgoing.lock().unwrap()[0] else {
+            panic!("expected initialize response");
+        };
+        assert!(response.result.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_on_initialize_can_reject() {
+        let (server, responses) = run(
+            |builder| builder.on_initialize(|_| Err(anyhow::anyhow!("not allowed"))),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "tools/list", json!({})),
+            ],
+        )
+        .await;
+        assert_eq!(responses[0].error.as_ref().unwrap().message, "not allowed");
+        assert!(responses[1].error.is_some());
+        assert!(!server.is_initialized());
+    }
+
+    #[tokio::test]
+    async fn test_negotiates_protocol_version() {
+        let (server, responses) = run(
+            |builder| builder,
+            vec![request(
+                0,
+                "initialize",
+                initialize_params_with_version("2024-11-05"),
+            )],
+        )
+        .await;
+        assert_eq!(
+            responses[0].result.as_ref().unwrap()["protocolVersion"],
+            "2024-11-05"
+        );
+        assert_eq!(
+            server.protocol_version(),
+            Some(ProtocolVersion::V2024_11_05)
+        );
+
+        // unsupported versions are answered with the preferred one
+        let (server, responses) = run(
+            |builder| {
+                builder
+                    .protocol_versions([ProtocolVersion::V2025_03_26, ProtocolVersion::V2024_11_05])
+            },
+            vec![request(
+                0,
+                "initialize",
+                initialize_params_with_version("2099-01-01"),
+            )],
+        )
+        .await;
+        assert_eq!(
+            responses[0].result.as_ref().unwrap()["protocolVersion"],
+            "2025-03-26"
+        );
+        assert_eq!(
+            server.protocol_version(),
+            Some(ProtocolVersion::V2025_03_26)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tools_list_changed() {
+        struct Echo;
+
+        impl crate::tools::Tool for Echo {
+            fn name(&self) -> String {
+                "echo".to_string()
+            }
+
+            fn description(&self) -> String {
+                "Echo the input".to_string()
+            }
+
+            fn input_schema(&self) -> serde_json::Value {
+                json!({"type": "object"})
+            }
+
+            fn call(
+                &self,
+                input: Option<serde_json::Value>,
+            ) -> Result<crate::types::CallToolResponse> {
+                Ok(crate::types::CallToolResponse {
+                    content: vec![crate::types::ToolResponseContent::text(
+                        input.unwrap_or_default().to_string(),
+                    )],
+                    is_error: None,
+                    structured_content: None,
+                    meta: None,
+                })
+            }
+        }
+
+        // the handle only exists once the server is built, the hook runs later
+        let handle = Arc::new(std::sync::OnceLock::<ToolsHandle>::new());
+        let hook_handle = handle.clone();
+        let transport = QueueTransport::new(vec![
+            request(0, "initialize", initialize_params()),
+            initialized(),
+            request(1, "tools/list", json!({})),
+        ]);
+        let outgoing = transport.outgoing.clone();
+        let server = Server::builder(transport)
+            .tools(Tools::default())
+            .on_initialized(move || {
+                let tools = hook_handle.get().unwrap();
+                tools.set_enabled("echo", true).unwrap();
+                // already enabled, nothing to announce
+                tools.set_enabled("echo", true).unwrap();
+                tools.update(|_| ((), false)).unwrap();
+                assert!(!tools.remove_tool("missing").unwrap());
+            })
+            .build();
+        assert!(handle.set(server.tools_handle().unwrap()).is_ok());
+        // changes before initialization are not announced
+        let tools = server.tools_handle().unwrap();
+        tools.add_tool(Echo).unwrap();
+        assert!(tools.add_tool(Echo).is_err());
+        assert!(tools.set_enabled("echo", false).unwrap());
+        assert!(server.listen().await.is_err());
+
+        let outgoing = outgoing.lock().unwrap();
+        assert_eq!(outgoing.len(), 3);
+        let JsonRpcMessage::Notification(notification) = &outgoing[1] else {
+            panic!("expected list_changed notification");
+        };
+        assert_eq!(notification.method, "notifications/tools/list_changed");
+        let JsonRpcMessage::Response(list) = &outgoing[2] else {
+            panic!("expected tools/list response");
+        };
+        assert_eq!(list.result.as_ref().unwrap()["tools"][0]["name"], "echo");
+    }
+
+    #[tokio::test]
+    async fn test_paginates_tools_list() {
+        struct Named(&'static str);
+
+        impl crate::tools::Tool for Named {
+            fn name(&self) -> String {
+                self.0.to_string()
+            }
+
+            fn description(&self) -> String {
+                String::new()
+            }
+
+            fn input_schema(&self) -> serde_json::Value {
+                json!({"type": "object"})
+            }
+
+            fn call(
+                &self,
+                _input: Option<serde_json::Value>,
+            ) -> Result<crate::types::CallToolResponse> {
+                Ok(crate::types::CallToolResponse {
+                    content: vec![],
+                    is_error: None,
+                    structured_content: None,
+                    meta: None,
+                })
+            }
+        }
+
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        // the cursor the first page ends with, clients treat it as opaque
+        let cursor = URL_SAFE_NO_PAD.encode(r#"{"after":"a","index":1}"#);
+        let mut tools = Tools::default();
+        for name in ["c", "a", "b"] {
+            tools.add_tool(Named(name)).unwrap();
+        }
+        let (_, responses) = run(
+            |builder| builder.tools(tools).page_size(2),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "tools/list", json!({})),
+                request(2, "tools/list", json!({"cursor": cursor})),
+                request(3, "tools/list", json!({"cursor": "bogus"})),
+                request(4, "tools/call", json!({"name": "missing"})),
+            ],
+        )
+        .await;
+        let first = responses[1].result.as_ref().unwrap();
+        assert_eq!(first["tools"].as_array().unwrap().len(), 2);
+        assert_eq!(first["tools"][0]["name"], "c");
+        assert_eq!(first["nextCursor"], cursor);
+        let second = responses[2].result.as_ref().unwrap();
+        assert_eq!(second["tools"][0]["name"], "b");
+        assert!(second.get("nextCursor").is_none());
+        assert_eq!(
+            responses[3].error.as_ref().unwrap().code,
+            ErrorCode::InvalidParams as i32
+        );
+        assert_eq!(responses[4].result.as_ref().unwrap()["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn test_slow_tool_does_not_block_other_requests() {
+        struct Slow;
+
+        #[async_trait::async_trait]
+        impl crate::tools::AsyncTool for Slow {
+            fn name(&self) -> String {
+                "slow".to_string()
+            }
+
+            fn description(&self) -> String {
+                String::new()
+            }
+
+            fn input_schema(&self) -> serde_json::Value {
+                json!({"type": "object"})
+            }
+
+            async fn call(
+                &self,
+                _input: Option<serde_json::Value>,
+                _context: &crate::tools::ToolContext,
+            ) -> Result<crate::types::CallToolResponse> {
+                std::future::pending().await
+            }
+        }
+
+        let mut tools = Tools::default();
+        tools.add_async_tool(Slow).unwrap();
+        tools.timeout(std::time::Duration::from_millis(10));
+        let (_, responses) = run(
+            |builder| builder.tools(tools),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "tools/call", json!({"name": "slow"})),
+                request(2, "ping", json!({})),
+            ],
+        )
+        .await;
+        let ids: Vec<u64> = responses.iter().map(|response| response.id).collect();
+        assert_eq!(ids, [0, 2, 1]);
+        assert_eq!(
+            responses[2].result.as_ref().unwrap()["content"][0]["text"],
+            "Tool slow timed out after 10ms"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_context() {
+        /// Who is calling
+        #[crate::tools::tool]
+        async fn whoami(
+            context: &crate::tools::ToolContext,
+        ) -> Result<crate::types::CallToolResponse> {
+            context
+                .request
+                .progress()
+                .report(1.0, Some(2.0), Some("halfway"))?;
+            let name = context.client_info.as_ref().unwrap().name.clone();
+            Ok(crate::types::CallToolResponse {
+                content: vec![crate::types::ToolResponseContent::text(name)],
+                is_error: None,
+                structured_content: None,
+                meta: None,
+            })
+        }
+
+        let mut tools = Tools::default();
+        tools.add_async_tool(WhoamiTool).unwrap();
+        let transport = QueueTransport::new(vec![
+            request(0, "initialize", initialize_params()),
+            request(
+                1,
+                "tools/call",
+                json!({"name": "whoami", "_meta": {"progressToken": "call-1"}}),
+            ),
+        ]);
+        let outgoing = transport.outgoing.clone();
+        let server = Server::builder(transport).tools(tools).build();
+        assert!(server.listen().await.is_err());
+
+        let outgoing = outgoing.lock().unwrap();
+        let JsonRpcMessage::Notification(progress) = &outgoing[1] else {
+            panic!("expected progress notification");
+        };
+        assert_eq!(progress.method, "notifications/progress");
+        assert_eq!(
+            progress.params,
+            Some(
+                json!({"progressToken": "call-1", "progress": 1.0, "total": 2.0, "message": "halfway"})
+            )
+        );
+        let JsonRpcMessage::Response(response) = &outgoing[2] else {
+            panic!("expected tools/call response");
+        };
+        assert_eq!(
+            response.result.as_ref().unwrap()["content"][0]["text"],
+            "test"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_is_not_answered() {
+        /// Never finishes
+        #[crate::tools::tool]
+        async fn hang() -> Result<crate::types::CallToolResponse> {
+            std::future::pending().await
+        }
+
+        let mut tools = Tools::default();
+        tools.add_async_tool(HangTool).unwrap();
+        let (_, responses) = run(
+            |builder| builder.tools(tools),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "tools/call", json!({"name": "hang"})),
+                JsonRpcMessage::Notification(JsonRpcNotification {
+                    method: "notifications/cancelled".to_string(),
+                    params: Some(json!({"requestId": 1, "reason": "user gave up"})),
+                    ..Default::default()
+                }),
+                request(2, "ping", json!({})),
+            ],
+        )
+        .await;
+        let ids: Vec<u64> = responses.iter().map(|response| response.id).collect();
+        assert_eq!(ids, [0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_hung_request_does_not_block_shutdown() {
+        /// Never finishes
+        #[crate::tools::tool]
+        async fn hang() -> Result<crate::types::CallToolResponse> {
+            std::future::pending().await
+        }
+
+        let mut tools = Tools::default();
+        tools.add_async_tool(HangTool).unwrap();
+        let listen = run(
+            |builder| {
+                builder
+                    .tools(tools)
+                    .shutdown_grace(Duration::from_millis(10))
+            },
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "tools/call", json!({"name": "hang"})),
+            ],
+        );
+        let (_, responses) = tokio::time::timeout(Duration::from_secs(5), listen)
+            .await
+            .expect("listen should return after the grace period");
+        let ids: Vec<u64> = responses.iter().map(|response| response.id).collect();
+        assert_eq!(ids, [0]);
+    }
+
+    #[tokio::test]
+    async fn test_derives_capabilities_from_handlers() {
+        let (_, responses) = run(
+            |builder| {
+                builder
+                    .tools(Tools::default())
+                    .request_handler("resources/list", |_: ListRequest| Ok(json!({})))
+            },
+            vec![request(0, "initialize", initialize_params())],
+        )
+        .await;
+        let capabilities = &responses[0].result.as_ref().unwrap()["capabilities"];
+        assert_eq!(capabilities["tools"], json!({"listChanged": true}));
+        // a hand-written handler cannot change, so no list_changed
+        assert_eq!(capabilities["resources"], json!({}));
+        assert!(capabilities.get("prompts").is_none());
+        assert!(capabilities.get("logging").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prompts_handlers() {
+        use crate::prompts::Prompt;
+        use crate::types::{GetPromptResponse, PromptArgument, PromptMessage};
+        use std::collections::HashMap;
+
+        struct Greeting;
+
+        impl Prompt for Greeting {
+            fn name(&self) -> String {
+                "greeting".to_string()
+            }
+
+            fn description(&self) -> String {
+                "Greet someone".to_string()
+            }
+
+            fn arguments(&self) -> Vec<PromptArgument> {
+                vec![PromptArgument {
+                    name: "name".to_string(),
+                    description: None,
+                    required: Some(true),
+                }]
+            }
+
+            fn get(&self, arguments: HashMap<String, String>) -> Result<GetPromptResponse> {
+                Ok(GetPromptResponse {
+                    description: None,
+                    messages: vec![PromptMessage::user(format!("Hello {}", arguments["name"]))],
+                    meta: None,
+                })
+            }
+        }
+
+        let mut prompts = Prompts::default();
+        prompts.add_prompt(Greeting);
+        let (_, responses) = run(
+            |builder| builder.prompts(prompts),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "prompts/list", json!({})),
+                request(
+                    2,
+                    "prompts/get",
+                    json!({"name": "greeting", "arguments": {"name": "Ada"}}),
+                ),
+                request(3, "prompts/get", json!({"name": "greeting"})),
+            ],
+        )
+        .await;
+        let capabilities = &responses[0].result.as_ref().unwrap()["capabilities"];
+        assert_eq!(capabilities["prompts"], json!({"listChanged": true}));
+        assert_eq!(
+            responses[1].result.as_ref().unwrap()["prompts"][0]["name"],
+            "greeting"
+        );
+        assert_eq!(
+            responses[2].result.as_ref().unwrap()["messages"][0]["content"]["text"],
+            "Hello Ada"
+        );
+        assert_eq!(
+            responses[3].error.as_ref().unwrap().code,
+            ErrorCode::InvalidParams as i32
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resources_handlers() {
+        use crate::resources::ResourceProvider;
+        use crate::types::ResourceContents;
+        use url::Url;
+
+        struct Config;
+
+        impl ResourceProvider for Config {
+            fn uri(&self) -> Url {
+                Url::parse("file:///etc/app/config.json").unwrap()
+            }
+
+            fn name(&self) -> String {
+                "config".to_string()
+            }
+
+            fn read(&self) -> Result<Vec<ResourceContents>> {
+                Ok(vec![ResourceContents::text(self.uri(), "{}")])
+            }
+        }
+
+        let mut resources = Resources::default();
+        resources.add_resource(Config);
+        let (_, responses) = run(
+            |builder| builder.resources(resources),
+            vec![
+                request(0, "initialize", initialize_params()),
+                request(1, "resources/list", json!({})),
+                request(
+                    2,
+                    "resources/read",
+                    json!({"uri": "file:///etc/app/config.json"}),
+                ),
+                request(3, "resources/read", json!({"uri": "file:///missing"})),
+            ],
+        )
+        .await;
+        let capabilities = &responses[0].result.as_ref().unwrap()["capabilities"];
+        assert_eq!(
+            capabilities["resources"],
+            json!({"subscribe": true, "listChanged": true})
+        );
+        assert_eq!(
+            responses[1].result.as_ref().unwrap()["resources"][0]["mimeType"],
+            "application/json"
+        );
+        assert_eq!(
+            responses[2].result.as_ref().unwrap()["contents"][0],
+            json!({"uri": "file:///etc/app/config.json", "mimeType": "application/json", "text": "{}"})
+        );
+        assert_eq!(
+            responses[3].error.as_ref().unwrap().code,
+            ErrorCode::ResourceNotFound as i32
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resource_subscriptions() {
+        use crate::resources::ResourceProvider;
+        use crate::types::ResourceContents;
+        use url::Url;
+
+        struct Log;
+
+        impl ResourceProvider for Log {
+            fn uri(&self) -> Url {
+                Url::parse("file:///var/log/app.log").unwrap()
+            }
+
+            fn name(&self) -> String {
+                "log".to_string()
+            }
+
+            fn read(&self) -> Result<Vec<ResourceContents>> {
+                Ok(vec![])
+            }
+        }
+
+        let mut resources = Resources::default();
+        resources.add_resource(Log);
+        let updates = resources.updates();
+        let touch = updates.clone();
+        let transport = QueueTransport::new(vec![
+            request(0, "initialize", initialize_params()),
+            request(
+                1,
+                "resources/subscribe",
+                json!({"uri": "file:///var/log/app.log"}),
+            ),
+            request(2, "resources/subscribe", json!({"uri": "file:///missing"})),
+            request(3, "log/append", json!({})),
+            request(
+                4,
+                "resources/unsubscribe",
+                json!({"uri": "file:///var/log/app.log"}),
+            ),
+            request(5, "log/append", json!({})),
+        ]);
+        let outgoing = transport.outgoing.clone();
+        let server = Server::builder(transport)
+            .resources(resources)
+            .request_handler("log/append", move |_: serde_json::Value| {
+                touch.notify_updated(&Url::parse("file:///var/log/app.log")?)?;
+                Ok(json!({}))
+            })
+            .build();
+        assert!(server.listen().await.is_err());
+
+        let outgoing = outgoing.lock().unwrap();
+        let JsonRpcMessage::Response(initialize) = &outgoing[0] else {
+            panic!("expected initialize response");
+        };
+        assert_eq!(
+            initialize.result.as_ref().unwrap()["capabilities"]["resources"]["subscribe"],
+            true
+        );
+        let JsonRpcMessage::Response(missing) = &outgoing[2] else {
+            panic!("expected subscribe response");
+        };
+        assert_eq!(
+            missing.error.as_ref().unwrap().code,
+            ErrorCode::ResourceNotFound as i32
+        );
+        let JsonRpcMessage::Notification(notification) = &outgoing[3] else {
+            panic!("expected update notification");
+        };
+        assert_eq!(notification.method, "notifications/resources/updated");
+        assert_eq!(
+            notification.params,
+            Some(json!({"uri": "file:///var/log/app.log"}))
+        );
+        // nothing is sent once unsubscribed
+        assert_eq!(outgoing.len(), 7);
+        assert!(outgoing[4..]
+            .iter()
+            .all(|message| matches!(message, JsonRpcMessage::Response(_))));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_end_with_session() {
+        use crate::resources::ResourceProvider;
+        use crate::types::ResourceContents;
+        use url::Url;
+
+        struct Log;
+
+        impl ResourceProvider for Log {
+            fn uri(&self) -> Url {
+                Url::parse("file:///var/log/app.log").unwrap()
+            }
+
+            fn name(&self) -> String {
+                "log".to_string()
+            }
+
+            fn read(&self) -> Result<Vec<ResourceContents>> {
+                Ok(vec![])
+            }
+        }
+
+        let uri = Url::parse("file:///var/log/app.log").unwrap();
+        let mut resources = Resources::default();
+        resources.add_resource(Log);
+        let updates = resources.updates();
+        let transport = QueueTransport::new(vec![
+            request(0, "initialize", initialize_params()),
+            request(1, "resources/subscribe", json!({"uri": uri})),
+        ]);
+        let outgoing = transport.outgoing.clone();
+        let server = Server::builder(transport).resources(resources).build();
+        assert!(server.listen().await.is_err());
+
+        assert!(!updates.is_subscribed(&uri));
+        updates.notify_updated(&uri).unwrap();
+        assert_eq!(outgoing.lock().unwrap().len(), 2);
     }
 }
@@ -1,64 +1,1567 @@
-use crate::types::{CallToolRequest, CallToolResponse, ToolDefinition, ToolResponseContent};
+use crate::{
+    protocol::RequestContext,
+    schema,
+    types::{
+        CallToolRequest, CallToolResponse, ClientCapabilities, Implementation, ProtocolVersion,
+        ToolAnnotations, ToolDefinition, ToolResponseContent,
+    },
+};
 use anyhow::Result;
-use std::{collections::HashMap, sync::Arc};
+use async_trait::async_trait;
+pub use mcp_sdk_macros::tool;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    future::Future,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Semaphore;
 
 pub trait Tool: Send + Sync + 'static {
     fn name(&self) -> String;
+    fn title(&self) -> Option<String> {
+        None
+    }
     fn description(&self) -> String;
     fn input_schema(&self) -> serde_json::Value;
+    /// Tools declaring an output schema must return matching `structured_content`
+    fn output_schema(&self) -> Option<serde_json::Value> {
+        None
+    }
+    /// Hints such as whether the tool is read only, for hosts deciding when to ask for approval
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        None
+    }
     fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse>;
+    /// What the registry calls, override it to see the session and request behind the call
+    /// `call` is then only used outside of a registry
+    fn call_with_context(
+        &self,
+        input: Option<serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<CallToolResponse> {
+        self.call(input)
+    }
+    fn as_definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            title: self.title(),
+            description: Some(self.description()),
+            input_schema: self.input_schema(),
+            output_schema: self.output_schema(),
+            annotations: self.annotations(),
+        }
+    }
+}
+
+/// What a tool call knows besides its arguments, a default context belongs to no session
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    /// Id, `_meta`, cancellation and peer of the `tools/call` request
+    pub request: RequestContext,
+    pub client_info: Option<Implementation>,
+    pub client_capabilities: Option<ClientCapabilities>,
+    pub protocol_version: Option<ProtocolVersion>,
+}
+
+/// A tool whose call is awaited, for tools doing I/O or needing the calling context
+#[async_trait]
+pub trait AsyncTool: Send + Sync + 'static {
+    fn name(&self) -> String;
+    fn title(&self) -> Option<String> {
+        None
+    }
+    fn description(&self) -> String;
+    fn input_schema(&self) -> serde_json::Value;
+    /// Tools declaring an output schema must return matching `structured_content`
+    fn output_schema(&self) -> Option<serde_json::Value> {
+        None
+    }
+    /// Hints such as whether the tool is read only, for hosts deciding when to ask for approval
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        None
+    }
+    async fn call(
+        &self,
+        input: Option<serde_json::Value>,
+        context: &ToolContext,
+    ) -> Result<CallToolResponse>;
     fn as_definition(&self) -> ToolDefinition {
         ToolDefinition {
             name: self.name(),
+            title: self.title(),
             description: Some(self.description()),
             input_schema: self.input_schema(),
+            output_schema: self.output_schema(),
+            annotations: self.annotations(),
+        }
+    }
+}
+
+/// A tool whose input schema is generated from its arguments type
+/// arguments that do not match the type are reported as a tool error
+pub trait TypedTool: Send + Sync + 'static {
+    /// Should be a struct, MCP requires tool inputs to be objects
+    type Args: DeserializeOwned + JsonSchema;
+    /// `CallToolResponse` for plain content, `Structured<T>` for an output schema derived from `T`
+    type Output: ToolOutput;
+
+    fn name(&self) -> String;
+    fn title(&self) -> Option<String> {
+        None
+    }
+    fn description(&self) -> String;
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        None
+    }
+    fn call(&self, args: Self::Args) -> Result<Self::Output>;
+    /// What the registry calls, override it to see the session and request behind the call
+    fn call_with_context(&self, args: Self::Args, _context: &ToolContext) -> Result<Self::Output> {
+        self.call(args)
+    }
+}
+
+/// What a typed tool returns
+pub trait ToolOutput {
+    fn output_schema() -> Option<serde_json::Value>;
+    fn into_response(self) -> Result<CallToolResponse>;
+}
+
+impl ToolOutput for CallToolResponse {
+    fn output_schema() -> Option<serde_json::Value> {
+        None
+    }
+
+    fn into_response(self) -> Result<CallToolResponse> {
+        Ok(self)
+    }
+}
+
+/// A structured tool result, sent as `structuredContent` and as serialized text
+/// for clients that do not read structured content
+/// `T` should serialize to an object, MCP requires structured content to be one
+pub struct Structured<T>(pub T);
+
+impl<T: Serialize + JsonSchema> ToolOutput for Structured<T> {
+    fn output_schema() -> Option<serde_json::Value> {
+        Some(schemars::schema_for!(T).to_value())
+    }
+
+    fn into_response(self) -> Result<CallToolResponse> {
+        let value = serde_json::to_value(self.0)?;
+        Ok(CallToolResponse {
+            content: vec![ToolResponseContent::text(value.to_string())],
+            is_error: None,
+            structured_content: Some(value),
+            meta: None,
+        })
+    }
+}
+
+/// Exposes a typed tool through the untyped `Tool` interface
+struct Typed<T>(T);
+
+impl<T: TypedTool> Tool for Typed<T> {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn title(&self) -> Option<String> {
+        self.0.title()
+    }
+
+    fn description(&self) -> String {
+        self.0.description()
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.0.annotations()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        input_schema::<T::Args>()
+    }
+
+    fn output_schema(&self) -> Option<serde_json::Value> {
+        T::Output::output_schema()
+    }
+
+    fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+        self.0.call(parse_args(input)?)?.into_response()
+    }
+
+    fn call_with_context(
+        &self,
+        input: Option<serde_json::Value>,
+        context: &ToolContext,
+    ) -> Result<CallToolResponse> {
+        self.0
+            .call_with_context(parse_args(input)?, context)?
+            .into_response()
+    }
+}
+
+/// A layer around every tool call, for audit logging, authorization, redaction and the like
+/// it can change the request before calling `next` and the response after,
+/// or answer without calling `next` at all, an error is reported as a tool error
+#[async_trait]
+pub trait ToolMiddleware: Send + Sync + 'static {
+    async fn call(&self, request: CallToolRequest, next: Next<'_>) -> Result<CallToolResponse>;
+}
+
+/// The rest of the middleware stack, ending with the tool itself
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn ToolMiddleware>],
+    endpoint: &'a Endpoint,
+    context: &'a ToolContext,
+}
+
+impl Next<'_> {
+    /// Who is calling, for middleware such as authorization
+    pub fn context(&self) -> &ToolContext {
+        self.context
+    }
+
+    pub async fn run(self, request: CallToolRequest) -> Result<CallToolResponse> {
+        match self.middleware.split_first() {
+            Some((layer, middleware)) => {
+                let next = Next {
+                    middleware,
+                    endpoint: self.endpoint,
+                    context: self.context,
+                };
+                layer.call(request, next).await
+            }
+            None => Ok(self.endpoint.call(request, self.context).await),
+        }
+    }
+}
+
+/// Calls the tool named by the request the middleware passes on
+/// tools are looked up in a snapshot of the registry taken when the call started
+struct Endpoint {
+    tools: Arc<HashMap<String, Arc<Registered>>>,
+    disabled: Arc<HashSet<String>>,
+    limits: Arc<HashMap<String, Limits>>,
+    validate_arguments: bool,
+    /// Applies to tools without a timeout of their own
+    timeout: Option<Duration>,
+}
+
+impl Endpoint {
+    async fn call(&self, request: CallToolRequest, context: &ToolContext) -> CallToolResponse {
+        let registered = self
+            .tools
+            .get(&request.name)
+            .filter(|_| !self.disabled.contains(&request.name));
+        let Some(registered) = registered else {
+            return CallToolResponse {
+                content: vec![ToolResponseContent::text(format!(
+                    "Tool {} not found",
+                    request.name
+                ))],
+                is_error: Some(true),
+                structured_content: None,
+                meta: None,
+            };
+        };
+        if self.validate_arguments {
+            let arguments = request.arguments.clone().unwrap_or(serde_json::json!({}));
+            let violations = schema::validate(&registered.input_schema, &arguments);
+            if !violations.is_empty() {
+                return invalid_arguments(&request.name, violations);
+            }
+        }
+        let limits = self.limits.get(&request.name);
+        let timeout = limits.and_then(|limits| limits.timeout).or(self.timeout);
+        // waiting for a free slot counts towards the timeout
+        let call = async {
+            let _permit = match limits.and_then(|limits| limits.permits.as_ref()) {
+                Some(permits) => Some(permits.acquire().await?),
+                None => None,
+            };
+            registered.tool.call(request.arguments, context).await
+        };
+        let result = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, call).await {
+                Ok(result) => result,
+                Err(_) => {
+                    return CallToolResponse {
+                        content: vec![ToolResponseContent::text(format!(
+                            "Tool {} timed out after {:?}",
+                            request.name, timeout
+                        ))],
+                        is_error: Some(true),
+                        structured_content: None,
+                        meta: None,
+                    }
+                }
+            },
+            None => call.await,
+        };
+        match result {
+            Ok(response) => {
+                check_output(&request.name, registered.output_schema.as_ref(), response)
+            }
+            Err(e) => call_error(&request.name, e),
+        }
+    }
+}
+
+fn call_error(name: &str, error: anyhow::Error) -> CallToolResponse {
+    CallToolResponse {
+        content: vec![ToolResponseContent::text(format!(
+            "Error calling tool {}: {}",
+            name, error
+        ))],
+        is_error: Some(true),
+        structured_content: None,
+        meta: None,
+    }
+}
+
+/// One text item for the summary, then one per violation
+fn invalid_arguments(name: &str, violations: Vec<schema::Violation>) -> CallToolResponse {
+    let summary = ToolResponseContent::text(format!(
+        "Invalid arguments for tool {}: {} violation(s)",
+        name,
+        violations.len()
+    ));
+    let violations = violations
+        .into_iter()
+        .map(|violation| ToolResponseContent::text(violation.to_string()));
+    CallToolResponse {
+        content: std::iter::once(summary).chain(violations).collect(),
+        is_error: Some(true),
+        structured_content: None,
+        meta: None,
+    }
+}
+
+/// Make sure a tool declaring an output schema returned matching structured content
+fn check_output(
+    name: &str,
+    output_schema: Option<&serde_json::Value>,
+    response: CallToolResponse,
+) -> CallToolResponse {
+    let Some(output_schema) = output_schema else {
+        return response;
+    };
+    if response.is_error == Some(true) {
+        return response;
+    }
+    let violations = match &response.structured_content {
+        Some(content) => schema::validate(output_schema, content)
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        None => vec!["missing structured content".to_string()],
+    };
+    if violations.is_empty() {
+        return response;
+    }
+    CallToolResponse {
+        content: vec![ToolResponseContent::text(format!(
+            "Tool {} returned a result not matching its output schema: {}",
+            name,
+            violations.join(", ")
+        ))],
+        is_error: Some(true),
+        structured_content: None,
+        meta: None,
+    }
+}
+
+/// The json schema of a tool arguments type
+pub fn input_schema<A: JsonSchema>() -> serde_json::Value {
+    schemars::schema_for!(A).to_value()
+}
+
+/// Deserialize tool arguments, a call without arguments is treated as an empty object
+pub fn parse_args<A: DeserializeOwned>(input: Option<serde_json::Value>) -> Result<A> {
+    let input = input.unwrap_or_else(|| serde_json::json!({}));
+    serde_json::from_value(input).map_err(|e| anyhow::anyhow!("Invalid arguments: {e}"))
+}
+
+/// Lets synchronous tools share the registry with async ones
+/// calls run on the blocking thread pool, so they neither stall the runtime nor escape
+/// the timeout, a call that timed out still runs to completion in the background
+struct SyncTool<T>(Arc<T>);
+
+#[async_trait]
+impl<T: Tool> AsyncTool for SyncTool<T> {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn title(&self) -> Option<String> {
+        self.0.title()
+    }
+
+    fn description(&self) -> String {
+        self.0.description()
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.0.annotations()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        self.0.input_schema()
+    }
+
+    fn output_schema(&self) -> Option<serde_json::Value> {
+        self.0.output_schema()
+    }
+
+    async fn call(
+        &self,
+        input: Option<serde_json::Value>,
+        context: &ToolContext,
+    ) -> Result<CallToolResponse> {
+        let tool = self.0.clone();
+        let context = context.clone();
+        tokio::task::spawn_blocking(move || tool.call_with_context(input, &context)).await?
+    }
+
+    fn as_definition(&self) -> ToolDefinition {
+        self.0.as_definition()
+    }
+}
+
+/// A tool registered under another name, see `Tools::prefixed`
+struct Renamed {
+    name: String,
+    tool: Arc<dyn AsyncTool>,
+}
+
+#[async_trait]
+impl AsyncTool for Renamed {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn title(&self) -> Option<String> {
+        self.tool.title()
+    }
+
+    fn description(&self) -> String {
+        self.tool.description()
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.tool.annotations()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        self.tool.input_schema()
+    }
+
+    fn output_schema(&self) -> Option<serde_json::Value> {
+        self.tool.output_schema()
+    }
+
+    async fn call(
+        &self,
+        input: Option<serde_json::Value>,
+        context: &ToolContext,
+    ) -> Result<CallToolResponse> {
+        self.tool.call(input, context).await
+    }
+
+    fn as_definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            ..self.tool.as_definition()
         }
     }
 }
 
+/// What happens to a tool added or merged under a name that is already registered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Fail without changes
+    #[default]
+    Error,
+    /// The merged tool replaces the existing one, keeping its position
+    Replace,
+    /// The existing tool stays, the merged one is dropped
+    Keep,
+}
+
+/// A tool in a registry, with its schemas generated once instead of on every call
+struct Registered {
+    tool: Arc<dyn AsyncTool>,
+    input_schema: serde_json::Value,
+    output_schema: Option<serde_json::Value>,
+}
+
+impl Registered {
+    fn new(tool: Arc<dyn AsyncTool>) -> Arc<Self> {
+        Arc::new(Self {
+            input_schema: tool.input_schema(),
+            output_schema: tool.output_schema(),
+            tool,
+        })
+    }
+}
+
+type ToolOrder = dyn Fn(&ToolDefinition, &ToolDefinition) -> Ordering + Send + Sync;
+
 #[derive(Default)]
 pub struct Tools {
-    tools: HashMap<String, Arc<dyn Tool>>,
+    /// Shared with the calls in flight, copied when changed while they run
+    tools: Arc<HashMap<String, Arc<Registered>>>,
+    /// Names in registration order
+    order: Vec<String>,
+    sort: Option<Box<ToolOrder>>,
+    disabled: Arc<HashSet<String>>,
+    validate_arguments: bool,
+    middleware: Vec<Arc<dyn ToolMiddleware>>,
+    /// Applies to tools without a timeout of their own
+    timeout: Option<Duration>,
+    limits: Arc<HashMap<String, Limits>>,
+    on_duplicate: DuplicatePolicy,
+}
+
+/// Execution limits of a single tool
+#[derive(Clone, Default)]
+struct Limits {
+    timeout: Option<Duration>,
+    permits: Option<Arc<Semaphore>>,
 }
 
 impl Tools {
-    pub fn add_tool(&mut self, tool: impl Tool) {
-        self.tools.insert(tool.name(), Arc::new(tool));
+    /// Fails without changes if the name is taken, unless `on_duplicate` says otherwise
+    /// returns whether the tool was registered, false if the existing one was kept
+    pub fn add_tool(&mut self, tool: impl Tool) -> Result<bool> {
+        self.add_async_tool(SyncTool(Arc::new(tool)))
+    }
+
+    pub fn add_typed_tool(&mut self, tool: impl TypedTool) -> Result<bool> {
+        self.add_tool(Typed(tool))
     }
 
+    pub fn add_async_tool(&mut self, tool: impl AsyncTool) -> Result<bool> {
+        let name = tool.name();
+        if self.tools.contains_key(&name) {
+            match self.on_duplicate {
+                DuplicatePolicy::Error => anyhow::bail!("Duplicate tool name: {name}"),
+                DuplicatePolicy::Keep => return Ok(false),
+                DuplicatePolicy::Replace => {}
+            }
+        }
+        self.replace_async_tool(tool);
+        Ok(true)
+    }
+
+    /// Like `add_tool`, but a tool with the same name is always replaced
+    /// returns whether there was one
+    pub fn replace_tool(&mut self, tool: impl Tool) -> bool {
+        self.replace_async_tool(SyncTool(Arc::new(tool)))
+    }
+
+    pub fn replace_typed_tool(&mut self, tool: impl TypedTool) -> bool {
+        self.replace_tool(Typed(tool))
+    }
+
+    /// The replaced tool keeps its original position
+    /// but not its timeout and concurrency limit, those belonged to the old tool
+    pub fn replace_async_tool(&mut self, tool: impl AsyncTool) -> bool {
+        let name = tool.name();
+        if self.limits.contains_key(&name) {
+            Arc::make_mut(&mut self.limits).remove(&name);
+        }
+        let replaced = Arc::make_mut(&mut self.tools)
+            .insert(name.clone(), Registered::new(Arc::new(tool)))
+            .is_some();
+        if !replaced {
+            self.order.push(name);
+        }
+        replaced
+    }
+
+    /// What adding a tool under a name that is already registered does, failing by default
+    pub fn on_duplicate(&mut self, policy: DuplicatePolicy) {
+        self.on_duplicate = policy;
+    }
+
+    /// Returns whether a tool with that name was registered
+    pub fn remove_tool(&mut self, name: &str) -> bool {
+        Arc::make_mut(&mut self.disabled).remove(name);
+        Arc::make_mut(&mut self.limits).remove(name);
+        self.order.retain(|registered| registered != name);
+        Arc::make_mut(&mut self.tools).remove(name).is_some()
+    }
+
+    /// Register every tool of `other`, failing without changes if a name is taken
+    pub fn merge(&mut self, other: Tools) -> Result<()> {
+        self.merge_with(other, DuplicatePolicy::Error)
+    }
+
+    /// Register every tool of `other`, keeping whether they are enabled and their limits
+    /// a default timeout of `other` becomes the timeout of its tools without one,
+    /// its sort and argument validation are not carried over
+    /// fails without changes if `other` has middleware, it would no longer wrap its tools
+    pub fn merge_with(&mut self, other: Tools, policy: DuplicatePolicy) -> Result<()> {
+        if !other.middleware.is_empty() {
+            anyhow::bail!("Cannot merge tools with middleware, add it to the merged set instead");
+        }
+        let duplicates: Vec<&str> = other
+            .order
+            .iter()
+            .filter(|name| self.tools.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        if policy == DuplicatePolicy::Error && !duplicates.is_empty() {
+            anyhow::bail!("Duplicate tool names: {}", duplicates.join(", "));
+        }
+        for name in other.order {
+            if policy == DuplicatePolicy::Keep && self.tools.contains_key(&name) {
+                continue;
+            }
+            let tool = other.tools[&name].clone();
+            if Arc::make_mut(&mut self.tools)
+                .insert(name.clone(), tool)
+                .is_none()
+            {
+                self.order.push(name.clone());
+            }
+            let disabled = Arc::make_mut(&mut self.disabled);
+            if other.disabled.contains(&name) {
+                disabled.insert(name.clone());
+            } else {
+                disabled.remove(&name);
+            }
+            let mut limits = other.limits.get(&name).cloned().unwrap_or_default();
+            limits.timeout = limits.timeout.or(other.timeout);
+            Arc::make_mut(&mut self.limits).insert(name, limits);
+        }
+        Ok(())
+    }
+
+    /// The same tools with `prefix` put in front of their names, such as `fs.` for `fs.read_file`
+    /// to merge tool sets from several sources without their names colliding
+    pub fn prefixed(mut self, prefix: &str) -> Tools {
+        let rename = |name: &String| format!("{prefix}{name}");
+        let tools = self
+            .tools
+            .iter()
+            .map(|(name, registered)| {
+                let name = rename(name);
+                let tool = Arc::new(Renamed {
+                    name: name.clone(),
+                    tool: registered.tool.clone(),
+                });
+                let registered = Arc::new(Registered {
+                    tool,
+                    input_schema: registered.input_schema.clone(),
+                    output_schema: registered.output_schema.clone(),
+                });
+                (name, registered)
+            })
+            .collect();
+        let limits = self
+            .limits
+            .iter()
+            .map(|(name, limits)| (rename(name), limits.clone()))
+            .collect();
+        self.tools = Arc::new(tools);
+        self.order = self.order.iter().map(rename).collect();
+        self.disabled = Arc::new(self.disabled.iter().map(rename).collect());
+        self.limits = Arc::new(limits);
+        self
+    }
+
+    /// List tools in this order instead of registration order
+    pub fn sort_by(
+        &mut self,
+        compare: impl Fn(&ToolDefinition, &ToolDefinition) -> Ordering + Send + Sync + 'static,
+    ) {
+        self.sort = Some(Box::new(compare));
+    }
+
+    /// Disabled tools are hidden from `tools/list` and cannot be called
+    /// returns whether a tool with that name is registered
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        if !self.tools.contains_key(name) {
+            return false;
+        }
+        let disabled = Arc::make_mut(&mut self.disabled);
+        if enabled {
+            disabled.remove(name);
+        } else {
+            disabled.insert(name.to_string());
+        }
+        true
+    }
+
+    /// Check arguments against the tool's input schema before calling it
+    /// invalid arguments are answered with a tool error listing every violation
+    pub fn validate_arguments(&mut self, enabled: bool) {
+        self.validate_arguments = enabled;
+    }
+
+    /// Calls running longer are answered with a tool error, for tools without their own timeout
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Timeout of a single tool, overriding the default one
+    /// returns whether a tool with that name is registered
+    pub fn set_timeout(&mut self, name: &str, timeout: Duration) -> bool {
+        if !self.tools.contains_key(name) {
+            return false;
+        }
+        Arc::make_mut(&mut self.limits)
+            .entry(name.to_string())
+            .or_default()
+            .timeout = Some(timeout);
+        true
+    }
+
+    /// At most `max` calls of the tool run at once, further calls wait for one to finish
+    /// returns whether a tool with that name is registered
+    pub fn set_max_concurrency(&mut self, name: &str, max: NonZeroUsize) -> bool {
+        if !self.tools.contains_key(name) {
+            return false;
+        }
+        Arc::make_mut(&mut self.limits)
+            .entry(name.to_string())
+            .or_default()
+            .permits = Some(Arc::new(Semaphore::new(max.get())));
+        true
+    }
+
+    /// Wrap every tool call, the first middleware added is the outermost
+    pub fn add_middleware(&mut self, middleware: impl ToolMiddleware) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.tools.contains_key(name) && !self.disabled.contains(name)
+    }
+
+    /// In registration order unless a sort was configured, so listings are stable
     pub fn list_tools(&self) -> Vec<ToolDefinition> {
-        self.tools
-            .values()
-            .map(|tool| tool.as_definition())
+        let mut tools: Vec<ToolDefinition> = self
+            .order
+            .iter()
+            .filter(|name| !self.disabled.contains(*name))
+            .map(|name| self.tools[name].tool.as_definition())
+            .collect();
+        if let Some(sort) = &self.sort {
+            tools.sort_by(|a, b| sort(a, b));
+        }
+        tools
+    }
+
+    /// Call a tool outside of a session, with a default context
+    pub fn call_tool(
+        &self,
+        request: CallToolRequest,
+    ) -> impl Future<Output = CallToolResponse> + Send + 'static {
+        self.call_tool_with_context(request, ToolContext::default())
+    }
+
+    /// The registry is snapshotted right away, the returned future does not borrow it
+    /// middleware runs even for unknown tools, the tool is looked up by the name it passes on
+    pub fn call_tool_with_context(
+        &self,
+        request: CallToolRequest,
+        context: ToolContext,
+    ) -> impl Future<Output = CallToolResponse> + Send + 'static {
+        let endpoint = Endpoint {
+            tools: self.tools.clone(),
+            disabled: self.disabled.clone(),
+            limits: self.limits.clone(),
+            validate_arguments: self.validate_arguments,
+            timeout: self.timeout,
+        };
+        let middleware = self.middleware.clone();
+        async move {
+            let name = request.name.clone();
+            let next = Next {
+                middleware: &middleware,
+                endpoint: &endpoint,
+                context: &context,
+            };
+            next.run(request)
+                .await
+                .unwrap_or_else(|e| call_error(&name, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl Tool for Echo {
+        fn name(&self) -> String {
+            "echo".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Echo the input".to_string()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+            Ok(CallToolResponse {
+                content: vec![ToolResponseContent::text(
+                    input.unwrap_or_default().to_string(),
+                )],
+                is_error: None,
+                structured_content: None,
+                meta: None,
+            })
+        }
+    }
+
+    struct Named(&'static str);
+
+    impl Tool for Named {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        fn call(&self, _input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+            Ok(CallToolResponse {
+                content: vec![ToolResponseContent::text(self.0)],
+                is_error: None,
+                structured_content: None,
+                meta: None,
+            })
+        }
+    }
+
+    fn names(tools: &Tools) -> Vec<String> {
+        tools
+            .list_tools()
+            .into_iter()
+            .map(|tool| tool.name)
             .collect()
     }
 
-    pub fn call_tool(&self, request: CallToolRequest) -> CallToolResponse {
-        let tool = self.tools.get(&request.name);
-        if tool.is_none() {
-            return CallToolResponse {
-                content: vec![ToolResponseContent::Text {
-                    text: format!("Tool {} not found", request.name),
-                }],
-                is_error: Some(true),
+    fn call(name: &str) -> CallToolRequest {
+        CallToolRequest {
+            name: name.to_string(),
+            arguments: None,
+            meta: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tools_are_hidden() {
+        let mut tools = Tools::default();
+        tools.add_tool(Echo).unwrap();
+        assert!(tools.set_enabled("echo", false));
+        assert!(!tools.set_enabled("missing", false));
+
+        assert!(tools.list_tools().is_empty());
+        assert_eq!(tools.call_tool(call("echo")).await.is_error, Some(true));
+
+        tools.set_enabled("echo", true);
+        assert_eq!(tools.list_tools().len(), 1);
+        assert_eq!(tools.call_tool(call("echo")).await.is_error, None);
+    }
+
+    #[test]
+    fn test_list_order() {
+        let mut tools = Tools::default();
+        for name in ["c", "a", "b"] {
+            tools.add_tool(Named(name)).unwrap();
+        }
+        assert!(tools.replace_tool(Named("a")));
+        assert_eq!(names(&tools), ["c", "a", "b"]);
+
+        tools.remove_tool("a");
+        tools.add_tool(Named("a")).unwrap();
+        assert_eq!(names(&tools), ["c", "b", "a"]);
+
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(names(&tools), ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge() {
+        let mut fs = Tools::default();
+        fs.add_tool(Echo).unwrap();
+        fs.add_tool(Named("read")).unwrap();
+        fs.set_enabled("read", false);
+        let fs = fs.prefixed("fs.");
+        assert_eq!(names(&fs), ["fs.echo"]);
+
+        let mut tools = Tools::default();
+        tools.add_tool(Named("read")).unwrap();
+        tools.merge(fs).unwrap();
+        assert_eq!(names(&tools), ["read", "fs.echo"]);
+        assert!(tools.is_registered("fs.read"));
+        assert!(!tools.is_enabled("fs.read"));
+
+        let mut request = call("fs.echo");
+        request.arguments = Some(serde_json::json!({"a": 1}));
+        let response = tools.call_tool(request).await;
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, r#"{"a":1}"#);
+
+        let mut other = Tools::default();
+        other.add_tool(Named("new")).unwrap();
+        other.add_tool(Echo).unwrap();
+        other.add_tool(Named("read")).unwrap();
+        let error = tools.merge(other).unwrap_err();
+        assert_eq!(error.to_string(), "Duplicate tool names: read");
+        assert_eq!(names(&tools), ["read", "fs.echo"]);
+
+        let mut other = Tools::default();
+        other.add_tool(Echo).unwrap();
+        other.add_tool(Named("read")).unwrap();
+        tools.merge_with(other, DuplicatePolicy::Keep).unwrap();
+        assert_eq!(names(&tools), ["read", "fs.echo", "echo"]);
+
+        let mut other = Tools::default();
+        other.add_tool(Named("guarded")).unwrap();
+        other.add_middleware(Alias);
+        assert!(tools.merge(other).is_err());
+        assert!(!tools.is_registered("guarded"));
+    }
+
+    #[test]
+    fn test_duplicate_tool() {
+        let mut tools = Tools::default();
+        assert!(tools.add_tool(Named("read")).unwrap());
+        let error = tools.add_tool(Named("read")).unwrap_err();
+        assert_eq!(error.to_string(), "Duplicate tool name: read");
+        tools.add_typed_tool(Add).unwrap();
+        assert!(tools.add_async_tool(Sleep).is_ok());
+        assert!(tools.add_typed_tool(Add).is_err());
+        assert_eq!(names(&tools), ["read", "add", "sleep"]);
+
+        tools.on_duplicate(DuplicatePolicy::Keep);
+        assert!(!tools.add_tool(Named("read")).unwrap());
+        tools.on_duplicate(DuplicatePolicy::Replace);
+        assert!(tools.add_tool(Named("read")).unwrap());
+        assert!(!tools.replace_tool(Named("write")));
+        assert_eq!(names(&tools), ["read", "add", "sleep", "write"]);
+    }
+
+    struct Sleep;
+
+    #[async_trait]
+    impl AsyncTool for Sleep {
+        fn name(&self) -> String {
+            "sleep".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Sleep for a moment".to_string()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        async fn call(
+            &self,
+            _input: Option<serde_json::Value>,
+            _context: &ToolContext,
+        ) -> Result<CallToolResponse> {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            Err(anyhow::anyhow!("woke up"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_tools() {
+        let mut tools = Tools::default();
+        tools.add_tool(Echo).unwrap();
+        tools.add_async_tool(Sleep).unwrap();
+        assert_eq!(names(&tools), ["echo", "sleep"]);
+
+        let response = tools.call_tool(call("sleep")).await;
+        assert_eq!(response.is_error, Some(true));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "Error calling tool sleep: woke up");
+    }
+
+    #[derive(serde::Deserialize, JsonSchema)]
+    struct AddArgs {
+        a: i64,
+        b: i64,
+    }
+
+    #[derive(Serialize, JsonSchema)]
+    struct Sum {
+        sum: i64,
+    }
+
+    struct Add;
+
+    impl TypedTool for Add {
+        type Args = AddArgs;
+        type Output = Structured<Sum>;
+
+        fn name(&self) -> String {
+            "add".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Add two numbers".to_string()
+        }
+
+        fn call(&self, args: AddArgs) -> Result<Structured<Sum>> {
+            Ok(Structured(Sum {
+                sum: args.a + args.b,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_tools() {
+        let mut tools = Tools::default();
+        tools.add_typed_tool(Add).unwrap();
+
+        let definition = &tools.list_tools()[0];
+        let schema = &definition.input_schema;
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["a"]["type"], "integer");
+        assert_eq!(schema["required"], serde_json::json!(["a", "b"]));
+        let output_schema = definition.output_schema.as_ref().unwrap();
+        assert_eq!(output_schema["required"], serde_json::json!(["sum"]));
+
+        let mut request = call("add");
+        request.arguments = Some(serde_json::json!({"a": 1, "b": 2}));
+        let response = tools.call_tool(request).await;
+        assert_eq!(
+            response.structured_content,
+            Some(serde_json::json!({"sum": 3}))
+        );
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, r#"{"sum":3}"#);
+
+        let mut request = call("add");
+        request.arguments = Some(serde_json::json!({"a": "one"}));
+        let response = tools.call_tool(request).await;
+        assert_eq!(response.is_error, Some(true));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert!(text.starts_with("Error calling tool add: Invalid arguments"));
+    }
+
+    /// Multiply two numbers
+    #[tool]
+    fn multiply(
+        /// The first factor
+        a: i64,
+        b: Option<i64>,
+    ) -> Result<CallToolResponse> {
+        Ok(CallToolResponse {
+            content: vec![ToolResponseContent::text((a * b.unwrap_or(1)).to_string())],
+            is_error: None,
+            structured_content: None,
+            meta: None,
+        })
+    }
+
+    #[tool(name = "sleepy_echo", description = "Echo after a nap")]
+    async fn echo_later(text: String) -> Result<CallToolResponse> {
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        Ok(CallToolResponse {
+            content: vec![ToolResponseContent::text(text)],
+            is_error: None,
+            structured_content: None,
+            meta: None,
+        })
+    }
+
+    /// Add two numbers
+    #[tool(title = "Sum", read_only, open_world = false)]
+    fn sum(a: i64, b: i64) -> Result<Structured<Sum>> {
+        Ok(Structured(Sum { sum: a + b }))
+    }
+
+    /// Named like the arguments struct the macro generates used to be
+    #[derive(serde::Deserialize, JsonSchema)]
+    struct Args {
+        text: String,
+    }
+
+    #[tool]
+    fn shout(args: Args) -> Result<CallToolResponse> {
+        Ok(CallToolResponse {
+            content: vec![ToolResponseContent::text(args.text.to_uppercase())],
+            is_error: None,
+            structured_content: None,
+            meta: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tool_macro() {
+        let mut tools = Tools::default();
+        MultiplyTool::register(&mut tools).unwrap();
+        EchoLaterTool::register(&mut tools).unwrap();
+        SumTool::register(&mut tools).unwrap();
+
+        let definitions = tools.list_tools();
+        assert_eq!(definitions[0].name, "multiply");
+        assert_eq!(
+            definitions[0].description.as_deref(),
+            Some("Multiply two numbers")
+        );
+        let schema = &definitions[0].input_schema;
+        assert_eq!(schema["properties"]["a"]["description"], "The first factor");
+        assert_eq!(schema["required"], serde_json::json!(["a"]));
+        assert!(definitions[0].output_schema.is_none());
+        assert!(definitions[2].output_schema.is_some());
+        assert_eq!(definitions[2].title.as_deref(), Some("Sum"));
+        assert!(definitions[2].annotations.as_ref().unwrap().title.is_none());
+        assert!(definitions[2].is_read_only());
+        assert!(!definitions[2].is_open_world());
+        assert!(!definitions[2].is_destructive());
+        assert!(definitions[0].annotations.is_none());
+        assert_eq!(definitions[1].name, "sleepy_echo");
+        assert_eq!(
+            definitions[1].description.as_deref(),
+            Some("Echo after a nap")
+        );
+
+        let mut request = call("multiply");
+        request.arguments = Some(serde_json::json!({"a": 6, "b": 7}));
+        let response = tools.call_tool(request).await;
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "42");
+
+        ShoutTool::register(&mut tools).unwrap();
+        let mut request = call("shout");
+        request.arguments = Some(serde_json::json!({"args": {"text": "hi"}}));
+        let response = tools.call_tool(request).await;
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "HI");
+
+        let mut request = call("sleepy_echo");
+        request.arguments = Some(serde_json::json!({"text": "hi"}));
+        let response = tools.call_tool(request).await;
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "hi");
+    }
+
+    #[tokio::test]
+    async fn test_validate_arguments() {
+        let mut tools = Tools::default();
+        tools.add_typed_tool(Add).unwrap();
+
+        let mut request = call("add");
+        request.arguments = Some(serde_json::json!({"a": "one"}));
+        tools.validate_arguments(true);
+        let response = tools.call_tool(request).await;
+        assert_eq!(response.is_error, Some(true));
+        let texts: Vec<&str> = response
+            .content
+            .iter()
+            .map(|content| match content {
+                ToolResponseContent::Text { text, .. } => text.as_str(),
+                _ => panic!("expected text content"),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "Invalid arguments for tool add: 2 violation(s)",
+                "/: missing required property 'b'",
+                "/a: expected integer, got string",
+            ]
+        );
+    }
+
+    /// Reports the protocol version of the session calling it
+    struct Version;
+
+    impl TypedTool for Version {
+        type Args = serde_json::Value;
+        type Output = CallToolResponse;
+
+        fn name(&self) -> String {
+            "version".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn call(&self, args: serde_json::Value) -> Result<CallToolResponse> {
+            self.call_with_context(args, &ToolContext::default())
+        }
+
+        fn call_with_context(
+            &self,
+            _args: serde_json::Value,
+            context: &ToolContext,
+        ) -> Result<CallToolResponse> {
+            let version = context.protocol_version.map(|version| version.to_string());
+            Ok(CallToolResponse {
+                content: vec![ToolResponseContent::text(
+                    version.unwrap_or_else(|| "none".to_string()),
+                )],
+                is_error: None,
+                structured_content: None,
                 meta: None,
-            };
+            })
         }
-        let arguments = request.arguments;
-        let result = tool.unwrap().call(arguments);
-        if result.is_err() {
-            return CallToolResponse {
-                content: vec![ToolResponseContent::Text {
-                    text: format!(
-                        "Error calling tool {}: {}",
-                        &request.name,
-                        result.err().unwrap()
-                    ),
-                }],
-                is_error: Some(true),
+    }
+
+    #[tokio::test]
+    async fn test_sync_tools_get_context() {
+        let mut tools = Tools::default();
+        tools.add_typed_tool(Version).unwrap();
+        let context = ToolContext {
+            protocol_version: Some(ProtocolVersion::V2025_03_26),
+            ..Default::default()
+        };
+        let response = tools.call_tool_with_context(call("version"), context).await;
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "2025-03-26");
+    }
+
+    /// Counts how often its schemas are generated
+    struct Counted(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl Tool for Counted {
+        fn name(&self) -> String {
+            "counted".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            serde_json::json!({"type": "object"})
+        }
+
+        fn output_schema(&self) -> Option<serde_json::Value> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Some(serde_json::json!({"type": "object"}))
+        }
+
+        fn call(&self, _input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+            Ok(CallToolResponse {
+                content: vec![],
+                is_error: None,
+                structured_content: Some(serde_json::json!({})),
                 meta: None,
-            };
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_schemas_generated_once() {
+        let generated = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut tools = Tools::default();
+        tools.add_tool(Counted(generated.clone())).unwrap();
+        tools.validate_arguments(true);
+        for _ in 0..3 {
+            let response = tools.call_tool(call("counted")).await;
+            assert_eq!(response.is_error, None);
+        }
+        assert_eq!(generated.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Declares an output schema it does not keep to
+    struct Liar;
+
+    impl Tool for Liar {
+        fn name(&self) -> String {
+            "liar".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        fn output_schema(&self) -> Option<serde_json::Value> {
+            Some(serde_json::json!({"type": "object", "required": ["sum"]}))
+        }
+
+        fn call(&self, input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+            Ok(CallToolResponse {
+                content: vec![],
+                is_error: None,
+                structured_content: input,
+                meta: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_output() {
+        let mut tools = Tools::default();
+        tools.add_tool(Liar).unwrap();
+
+        let mut request = call("liar");
+        request.arguments = Some(serde_json::json!({"sum": 1}));
+        assert_eq!(tools.call_tool(request).await.is_error, None);
+
+        for arguments in [None, Some(serde_json::json!({"total": 1}))] {
+            let mut request = call("liar");
+            request.arguments = arguments;
+            let response = tools.call_tool(request).await;
+            assert_eq!(response.is_error, Some(true));
+            assert!(response.structured_content.is_none());
+        }
+    }
+
+    /// Refuses calls with a `password` argument
+    struct Deny;
+
+    #[async_trait]
+    impl ToolMiddleware for Deny {
+        async fn call(&self, request: CallToolRequest, next: Next<'_>) -> Result<CallToolResponse> {
+            let arguments = request.arguments.as_ref();
+            if arguments.is_some_and(|arguments| arguments.get("password").is_some()) {
+                anyhow::bail!("passwords are not allowed");
+            }
+            next.run(request).await
         }
-        result.unwrap()
+    }
+
+    /// Adds an argument on the way in and truncates text on the way out
+    struct Rewrite;
+
+    #[async_trait]
+    impl ToolMiddleware for Rewrite {
+        async fn call(
+            &self,
+            mut request: CallToolRequest,
+            next: Next<'_>,
+        ) -> Result<CallToolResponse> {
+            request.arguments = Some(serde_json::json!({"user": "alice"}));
+            let mut response = next.run(request).await?;
+            for content in &mut response.content {
+                if let ToolResponseContent::Text { text, .. } = content {
+                    text.truncate(8);
+                }
+            }
+            Ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let mut tools = Tools::default();
+        tools.add_tool(Echo).unwrap();
+        tools.add_middleware(Deny);
+        tools.add_middleware(Rewrite);
+
+        let response = tools.call_tool(call("echo")).await;
+        assert_eq!(response.is_error, None);
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, r#"{"user":"#);
+
+        let mut request = call("echo");
+        request.arguments = Some(serde_json::json!({"password": "hunter2"}));
+        let response = tools.call_tool(request).await;
+        assert_eq!(response.is_error, Some(true));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "Error calling tool echo: passwords are not allowed");
+
+        let response = tools.call_tool(call("missing")).await;
+        assert_eq!(response.is_error, Some(true));
+    }
+
+    /// Routes calls of an old tool name to the new one
+    struct Alias;
+
+    #[async_trait]
+    impl ToolMiddleware for Alias {
+        async fn call(
+            &self,
+            mut request: CallToolRequest,
+            next: Next<'_>,
+        ) -> Result<CallToolResponse> {
+            if request.name == "say" {
+                request.name = "echo".to_string();
+            }
+            next.run(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_renames_tool() {
+        let mut tools = Tools::default();
+        tools.add_tool(Echo).unwrap();
+        tools.add_middleware(Alias);
+
+        let response = tools.call_tool(call("say")).await;
+        assert_eq!(response.is_error, None);
+
+        // the tool is found by the final name, so its state is the one that counts
+        tools.set_enabled("echo", false);
+        let response = tools.call_tool(call("say")).await;
+        assert_eq!(response.is_error, Some(true));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "Tool echo not found");
+    }
+
+    /// Tracks how many calls run at once
+    #[derive(Clone, Default)]
+    struct Busy {
+        running: Arc<std::sync::atomic::AtomicUsize>,
+        peak: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AsyncTool for Busy {
+        fn name(&self) -> String {
+            "busy".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        async fn call(
+            &self,
+            _input: Option<serde_json::Value>,
+            _context: &ToolContext,
+        ) -> Result<CallToolResponse> {
+            use std::sync::atomic::Ordering::SeqCst;
+            let running = self.running.fetch_add(1, SeqCst) + 1;
+            self.peak.fetch_max(running, SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, SeqCst);
+            Ok(CallToolResponse {
+                content: vec![],
+                is_error: None,
+                structured_content: None,
+                meta: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let busy = Busy::default();
+        let mut tools = Tools::default();
+        tools.add_async_tool(busy.clone()).unwrap();
+        assert!(tools.set_max_concurrency("busy", NonZeroUsize::new(2).unwrap()));
+        assert!(!tools.set_timeout("missing", Duration::from_secs(1)));
+
+        let calls: Vec<_> = (0..5)
+            .map(|_| tokio::spawn(tools.call_tool(call("busy"))))
+            .collect();
+        for call in calls {
+            assert_eq!(call.await.unwrap().is_error, None);
+        }
+        assert_eq!(busy.peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        tools.timeout(Duration::from_millis(5));
+        let response = tools.call_tool(call("busy")).await;
+        assert_eq!(response.is_error, Some(true));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "Tool busy timed out after 5ms");
+
+        tools.set_timeout("busy", Duration::from_secs(1));
+        assert_eq!(tools.call_tool(call("busy")).await.is_error, None);
+
+        // the replacement does not inherit the limits of the old tool
+        assert!(tools.replace_async_tool(busy.clone()));
+        assert_eq!(tools.call_tool(call("busy")).await.is_error, Some(true));
+    }
+
+    /// Blocks its thread for longer than any test waits
+    struct Stuck;
+
+    impl Tool for Stuck {
+        fn name(&self) -> String {
+            "stuck".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        fn call(&self, _input: Option<serde_json::Value>) -> Result<CallToolResponse> {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(CallToolResponse {
+                content: vec![],
+                is_error: None,
+                structured_content: None,
+                meta: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_tool_timeout() {
+        let mut tools = Tools::default();
+        tools.add_tool(Stuck).unwrap();
+        tools.timeout(Duration::from_millis(20));
+
+        let started = std::time::Instant::now();
+        let response = tools.call_tool(call("stuck")).await;
+        assert!(started.elapsed() < Duration::from_millis(400));
+        let ToolResponseContent::Text { text, .. } = &response.content[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text, "Tool stuck timed out after 20ms");
     }
 }
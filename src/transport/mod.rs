@@ -4,10 +4,16 @@
 //! defines transport layer types
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use tracing::{debug, debug_span, field::Empty, Span};
 
 mod stdio;
 pub use stdio::*;
 
+#[cfg(test)]
+mod queue;
+#[cfg(test)]
+pub(crate) use queue::QueueTransport;
+
 /// only JsonRpcMessage is supported for now
 /// https://spec.modelcontextprotocol.io/specification/basic/messages/
 pub type Message = JsonRpcMessage;
@@ -27,6 +33,106 @@ pub trait Transport: Send + Sync + 'static {
     fn close(&self) -> Result<()>;
 }
 
+/// Controls how much of a message payload transports write to the logs
+/// metadata (direction, method, id, size) is always recorded on the message span
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadLogging {
+    /// Log the full serialized message
+    #[default]
+    Full,
+    /// Log at most this many bytes of the serialized message
+    Truncated(usize),
+    /// Never log the payload
+    Redacted,
+}
+
+impl PayloadLogging {
+    fn render<'a>(&self, serialized: &'a str) -> std::borrow::Cow<'a, str> {
+        match *self {
+            PayloadLogging::Full => serialized.into(),
+            PayloadLogging::Truncated(max) if serialized.len() > max => {
+                let mut end = max;
+                while !serialized.is_char_boundary(end) {
+                    end -= 1;
+                }
+                format!("{}...", &serialized[..end]).into()
+            }
+            PayloadLogging::Truncated(_) => serialized.into(),
+            PayloadLogging::Redacted => "<redacted>".into(),
+        }
+    }
+}
+
+/// Direction of a message relative to the local side of the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Send,
+    Receive,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Send => "send",
+            Direction::Receive => "receive",
+        }
+    }
+}
+
+/// Create the span describing a single message on the wire
+pub(crate) fn message_span(direction: Direction, message: &Message, bytes: usize) -> Span {
+    let span = debug_span!(
+        "mcp_message",
+        direction = direction.as_str(),
+        method = Empty,
+        id = Empty,
+        bytes
+    );
+    match message {
+        JsonRpcMessage::Request(request) => {
+            span.record("method", request.method.as_str());
+            span.record("id", request.id);
+        }
+        JsonRpcMessage::Response(response) => {
+            span.record("id", response.id);
+        }
+        JsonRpcMessage::Notification(notification) => {
+            span.record("method", notification.method.as_str());
+        }
+    }
+    span
+}
+
+/// Log a message inside its span, honouring the payload logging setting
+/// transports must use this instead of printing, stdout may be the protocol stream itself
+pub(crate) fn trace_message(
+    direction: Direction,
+    message: &Message,
+    serialized: &str,
+    payload_logging: PayloadLogging,
+) {
+    let span = message_span(direction, message, serialized.len());
+    let _enter = span.enter();
+    debug!(payload = %payload_logging.render(serialized), "{}", direction.as_str());
+}
+
+/// Parse a received line, a line that is not a valid message is traced raw with the parse error
+pub(crate) fn parse_received(line: &str, payload_logging: PayloadLogging) -> Result<Message> {
+    let line = line.trim_end();
+    match serde_json::from_str(line) {
+        Ok(message) => {
+            trace_message(Direction::Receive, &message, line, payload_logging);
+            Ok(message)
+        }
+        Err(e) => {
+            let span = debug_span!("mcp_message", direction = "receive", bytes = line.len());
+            let _enter = span.enter();
+            debug!(payload = %payload_logging.render(line), error = %e, "malformed message");
+            Err(e.into())
+        }
+    }
+}
+
 /// Request ID type
 pub type RequestId = u64;
 /// JSON RPC version type
@@ -135,4 +241,29 @@ mod tests {
             _ => panic!("Expected Request variant"),
         }
     }
+
+    #[test]
+    fn test_payload_logging_render() {
+        let payload = r#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+        assert_eq!(PayloadLogging::Full.render(payload), payload);
+        assert_eq!(PayloadLogging::Redacted.render(payload), "<redacted>");
+        assert_eq!(
+            PayloadLogging::Truncated(10).render(payload),
+            r#"{"jsonrpc"..."#
+        );
+        assert_eq!(PayloadLogging::Truncated(1000).render(payload), payload);
+        // never split a multi-byte character
+        assert_eq!(PayloadLogging::Truncated(2).render("日本"), "...");
+    }
+
+    #[test]
+    fn test_parse_received() {
+        let message = parse_received(
+            "{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":1}\n",
+            PayloadLogging::Full,
+        )
+        .unwrap();
+        assert!(matches!(message, JsonRpcMessage::Request(request) if request.method == "ping"));
+        assert!(parse_received("{\"jsonrpc\":", PayloadLogging::Full).is_err());
+    }
 }
@@ -0,0 +1,69 @@
+use super::{JsonRpcMessage, JsonRpcRequest, JsonRpcResponse, Message, Transport};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+type Responder = dyn Fn(&JsonRpcRequest) -> serde_json::Value + Send + Sync;
+
+/// Test transport that replays queued messages and records everything sent
+/// receiving fails once the queue is empty, which ends the listen loop
+#[derive(Default)]
+pub(crate) struct QueueTransport {
+    pub(crate) incoming: Mutex<VecDeque<Message>>,
+    pub(crate) outgoing: Arc<Mutex<Vec<Message>>>,
+    /// Result for each request sent, queued as its response
+    responder: Option<Box<Responder>>,
+}
+
+impl QueueTransport {
+    pub(crate) fn new(messages: Vec<Message>) -> Self {
+        Self {
+            incoming: Mutex::new(messages.into()),
+            outgoing: Default::default(),
+            responder: None,
+        }
+    }
+
+    /// Answer every request sent with the result `reply` gives for it
+    pub(crate) fn with_responder(
+        mut self,
+        reply: impl Fn(&JsonRpcRequest) -> serde_json::Value + Send + Sync + 'static,
+    ) -> Self {
+        self.responder = Some(Box::new(reply));
+        self
+    }
+}
+
+impl Transport for QueueTransport {
+    fn send(&self, message: &Message) -> Result<()> {
+        self.outgoing.lock().unwrap().push(message.clone());
+        if let (Some(reply), JsonRpcMessage::Request(request)) = (&self.responder, message) {
+            let response = JsonRpcResponse {
+                id: request.id,
+                result: Some(reply(request)),
+                ..Default::default()
+            };
+            self.incoming
+                .lock()
+                .unwrap()
+                .push_back(JsonRpcMessage::Response(response));
+        }
+        Ok(())
+    }
+
+    fn receive(&self) -> Result<Message> {
+        self.incoming
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("Transport closed"))
+    }
+
+    fn open(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
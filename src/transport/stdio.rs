@@ -1,4 +1,4 @@
-use super::{Message, Transport};
+use super::{parse_received, trace_message, Direction, Message, PayloadLogging, Transport};
 use anyhow::Result;
 use std::io::{self, BufRead, Write};
 use std::process::{Child, Command, Stdio};
@@ -6,9 +6,21 @@ use std::sync::{Arc, Mutex};
 use tracing::debug;
 
 /// Stdio transport for server with json serialization
+/// all logging goes through `tracing`, stdout is reserved for the protocol stream
+/// it used to be a unit struct, construct it with `ServerStdioTransport::default()`
 /// TODO: support for other binary serialzation formats
 #[derive(Default, Clone)]
-pub struct ServerStdioTransport;
+pub struct ServerStdioTransport {
+    payload_logging: PayloadLogging,
+}
+
+impl ServerStdioTransport {
+    /// Set how much of each message payload is logged
+    pub fn with_payload_logging(mut self, payload_logging: PayloadLogging) -> Self {
+        self.payload_logging = payload_logging;
+        self
+    }
+}
 
 impl Transport for ServerStdioTransport {
     fn receive(&self) -> Result<Message> {
@@ -16,16 +28,14 @@ impl Transport for ServerStdioTransport {
         let mut reader = stdin.lock();
         let mut line = String::new();
         reader.read_line(&mut line)?;
-        debug!("Received: {line}");
-        let message: Message = serde_json::from_str(&line)?;
-        Ok(message)
+        parse_received(&line, self.payload_logging)
     }
 
     fn send(&self, message: &Message) -> Result<()> {
         let stdout = io::stdout();
         let mut writer = stdout.lock();
         let serialized = serde_json::to_string(message)?;
-        debug!("Sending: {serialized}");
+        trace_message(Direction::Send, message, &serialized, self.payload_logging);
         writer.write_all(serialized.as_bytes())?;
         writer.write_all(b"\n")?;
         writer.flush()?;
@@ -49,6 +59,7 @@ pub struct ClientStdioTransport {
     child: Arc<Mutex<Option<Child>>>,
     program: String,
     args: Vec<String>,
+    payload_logging: PayloadLogging,
 }
 
 impl ClientStdioTransport {
@@ -59,8 +70,15 @@ impl ClientStdioTransport {
             child: Arc::new(Mutex::new(None)),
             program: program.to_string(),
             args: args.iter().map(|&s| s.to_string()).collect(),
+            payload_logging: PayloadLogging::default(),
         })
     }
+
+    /// Set how much of each message payload is logged
+    pub fn with_payload_logging(mut self, payload_logging: PayloadLogging) -> Self {
+        self.payload_logging = payload_logging;
+        self
+    }
 }
 
 impl Transport for ClientStdioTransport {
@@ -74,9 +92,7 @@ impl Transport for ClientStdioTransport {
             .ok_or_else(|| anyhow::anyhow!("Transport not opened"))?;
         let mut line = String::new();
         stdout.read_line(&mut line)?;
-        println!("Received from process: {line}");
-        let message: Message = serde_json::from_str(&line)?;
-        Ok(message)
+        parse_received(&line, self.payload_logging)
     }
 
     fn send(&self, message: &Message) -> Result<()> {
@@ -88,7 +104,7 @@ impl Transport for ClientStdioTransport {
             .as_mut()
             .ok_or_else(|| anyhow::anyhow!("Transport not opened"))?;
         let serialized = serde_json::to_string(message)?;
-        debug!("Sending to process: {serialized}");
+        trace_message(Direction::Send, message, &serialized, self.payload_logging);
         stdin.write_all(serialized.as_bytes())?;
         stdin.write_all(b"\n")?;
         stdin.flush()?;
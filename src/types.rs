@@ -1,9 +1,68 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, str::FromStr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use url::Url;
 
-pub const LATEST_PROTOCOL_VERSION: &str = "2024-11-05";
+pub const LATEST_PROTOCOL_VERSION: &str = ProtocolVersion::LATEST.as_str();
+
+/// Protocol versions supported by this sdk, preferred first
+/// newer revisions are only used when the peer asks for them, until their features are complete
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[ProtocolVersion] = &[
+    ProtocolVersion::V2024_11_05,
+    ProtocolVersion::V2025_03_26,
+    ProtocolVersion::V2025_06_18,
+];
+
+/// A revision of the MCP specification
+/// versions are ordered by release date so behavior can be gated with comparisons
+/// https://spec.modelcontextprotocol.io/specification/basic/lifecycle/#version-negotiation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolVersion {
+    V2024_11_05,
+    V2025_03_26,
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    /// The version used by default, newer ones are negotiated on request
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V2024_11_05;
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolVersion::V2024_11_05 => "2024-11-05",
+            ProtocolVersion::V2025_03_26 => "2025-03-26",
+            ProtocolVersion::V2025_06_18 => "2025-06-18",
+        }
+    }
+
+    /// Pick the version a server answers with
+    /// the requested version if it is supported, otherwise the preferred (first) supported one
+    pub fn negotiate(requested: &str, supported: &[ProtocolVersion]) -> Option<ProtocolVersion> {
+        requested
+            .parse()
+            .ok()
+            .filter(|version| supported.contains(version))
+            .or_else(|| supported.first().copied())
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SUPPORTED_PROTOCOL_VERSIONS
+            .iter()
+            .find(|version| version.as_str() == s)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Unknown protocol version: {s}"))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -36,21 +95,142 @@ pub struct InitializeResponse {
 #[serde(default)]
 pub struct ServerCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<serde_json::Value>,
+    pub tools: Option<ToolsCapability>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub experimental: Option<serde_json::Value>,
+    pub experimental: Option<ExperimentalCapabilities>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub logging: Option<serde_json::Value>,
+    pub logging: Option<LoggingCapability>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompts: Option<PromptCapabilities>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub resources: Option<ResourceCapabilities>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completions: Option<CompletionsCapability>,
+}
+
+/// Non-standard capabilities keyed by name, unknown entries are preserved as is
+pub type ExperimentalCapabilities = HashMap<String, serde_json::Value>;
+
+/// Read an experimental capability as `C`
+/// returns `None` if it is absent or does not have the expected shape
+fn experimental_capability<C: DeserializeOwned>(
+    experimental: &Option<ExperimentalCapabilities>,
+    name: &str,
+) -> Option<C> {
+    let value = experimental.as_ref()?.get(name)?;
+    serde_json::from_value(value.clone()).ok()
+}
+
+fn insert_experimental(
+    experimental: &mut Option<ExperimentalCapabilities>,
+    name: String,
+    value: impl Serialize,
+) -> serde_json::Result<()> {
+    let value = serde_json::to_value(value)?;
+    experimental
+        .get_or_insert_with(Default::default)
+        .insert(name, value);
+    Ok(())
+}
+
+impl ServerCapabilities {
+    /// Read the experimental capability `name` as `C`
+    pub fn experimental_capability<C: DeserializeOwned>(&self, name: &str) -> Option<C> {
+        experimental_capability(&self.experimental, name)
+    }
+
+    /// Advertise an experimental capability, fails if `value` cannot be serialized
+    pub fn with_experimental(
+        mut self,
+        name: impl Into<String>,
+        value: impl Serialize,
+    ) -> serde_json::Result<Self> {
+        insert_experimental(&mut self.experimental, name.into(), value)?;
+        Ok(self)
+    }
+
+    /// Whether the server advertised the given capability
+    pub fn supports(&self, capability: ServerCapability) -> bool {
+        match capability {
+            ServerCapability::Tools => self.tools.is_some(),
+            ServerCapability::Prompts => self.prompts.is_some(),
+            ServerCapability::Resources => self.resources.is_some(),
+            ServerCapability::ResourceSubscriptions => self
+                .resources
+                .as_ref()
+                .and_then(|resources| resources.subscribe)
+                .unwrap_or(false),
+            ServerCapability::Logging => self.logging.is_some(),
+            ServerCapability::Completions => self.completions.is_some(),
+        }
+    }
+}
+
+/// A feature a server has to advertise before clients may use its methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerCapability {
+    Tools,
+    Prompts,
+    Resources,
+    ResourceSubscriptions,
+    Logging,
+    Completions,
+}
+
+impl ServerCapability {
+    /// The capability a client request needs, `None` for methods every server handles
+    pub fn required_for(method: &str) -> Option<Self> {
+        match method {
+            "tools/list" | "tools/call" => Some(ServerCapability::Tools),
+            "prompts/list" | "prompts/get" => Some(ServerCapability::Prompts),
+            "resources/list" | "resources/read" | "resources/templates/list" => {
+                Some(ServerCapability::Resources)
+            }
+            "resources/subscribe" | "resources/unsubscribe" => {
+                Some(ServerCapability::ResourceSubscriptions)
+            }
+            "logging/setLevel" => Some(ServerCapability::Logging),
+            "completion/complete" => Some(ServerCapability::Completions),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerCapability::Tools => "tools",
+            ServerCapability::Prompts => "prompts",
+            ServerCapability::Resources => "resources",
+            ServerCapability::ResourceSubscriptions => "resources.subscribe",
+            ServerCapability::Logging => "logging",
+            ServerCapability::Completions => "completions",
+        }
+    }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ToolsCapability {
+    /// Whether the server emits `notifications/tools/list_changed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_changed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct LoggingCapability {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CompletionsCapability {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
 pub struct PromptCapabilities {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
@@ -58,7 +238,9 @@ pub struct PromptCapabilities {
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
 pub struct ResourceCapabilities {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub subscribe: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
@@ -66,15 +248,48 @@ pub struct ResourceCapabilities {
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
 pub struct ClientCapabilities {
-    pub experimental: Option<serde_json::Value>,
-    pub sampling: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<ExperimentalCapabilities>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub roots: Option<RootCapabilities>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<ElicitationCapability>,
+}
+
+impl ClientCapabilities {
+    /// Read the experimental capability `name` as `C`
+    pub fn experimental_capability<C: DeserializeOwned>(&self, name: &str) -> Option<C> {
+        experimental_capability(&self.experimental, name)
+    }
+
+    /// Advertise an experimental capability, fails if `value` cannot be serialized
+    pub fn with_experimental(
+        mut self,
+        name: impl Into<String>,
+        value: impl Serialize,
+    ) -> serde_json::Result<Self> {
+        insert_experimental(&mut self.experimental, name.into(), value)?;
+        Ok(self)
+    }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct SamplingCapability {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ElicitationCapability {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
 pub struct RootCapabilities {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
@@ -82,9 +297,70 @@ pub struct RootCapabilities {
 #[serde(rename_all = "camelCase")]
 pub struct ToolDefinition {
     pub name: String,
+    /// Human readable name for display
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub input_schema: serde_json::Value,
+    /// Schema of the `structuredContent` the tool returns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+impl ToolDefinition {
+    /// Whether the tool leaves its environment unchanged, false unless annotated
+    pub fn is_read_only(&self) -> bool {
+        self.hint(|annotations| annotations.read_only_hint)
+            .unwrap_or(false)
+    }
+
+    /// Whether changes may be destructive rather than only additive, true unless annotated
+    /// always false for read only tools, the hint only applies to tools that make changes
+    pub fn is_destructive(&self) -> bool {
+        !self.is_read_only()
+            && self
+                .hint(|annotations| annotations.destructive_hint)
+                .unwrap_or(true)
+    }
+
+    /// Whether repeating a call with the same arguments has no further effect, false unless annotated
+    /// always false for read only tools, the hint only applies to tools that make changes
+    pub fn is_idempotent(&self) -> bool {
+        !self.is_read_only()
+            && self
+                .hint(|annotations| annotations.idempotent_hint)
+                .unwrap_or(false)
+    }
+
+    /// Whether the tool talks to an open world of external entities, true unless annotated
+    pub fn is_open_world(&self) -> bool {
+        self.hint(|annotations| annotations.open_world_hint)
+            .unwrap_or(true)
+    }
+
+    fn hint(&self, hint: impl Fn(&ToolAnnotations) -> Option<bool>) -> Option<bool> {
+        self.annotations.as_ref().and_then(hint)
+    }
+}
+
+/// Hints about a tool's behavior, clients must not rely on them for security
+/// when they come from an untrusted server
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -102,27 +378,198 @@ pub struct CallToolResponse {
     pub content: Vec<ToolResponseContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_error: Option<bool>,
+    /// Result matching the tool's output schema, `content` should carry it serialized too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
     #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
     pub meta: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+/// A content block of a tool result or prompt message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ToolResponseContent {
-    #[serde(rename = "text")]
-    Text { text: String },
-    #[serde(rename = "image")]
-    Image { data: String, mime_type: String },
-    #[serde(rename = "resource")]
-    Resource { resource: ResourceContents },
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        annotations: Option<Annotations>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Image {
+        /// Base64 encoded image data
+        data: String,
+        mime_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        annotations: Option<Annotations>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Audio {
+        /// Base64 encoded audio data
+        data: String,
+        mime_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        annotations: Option<Annotations>,
+    },
+    /// Resource embedded in the result
+    Resource {
+        resource: ResourceContents,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        annotations: Option<Annotations>,
+    },
+    /// Link to a resource the client can read or subscribe to
+    ResourceLink(Resource),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ToolResponseContent {
+    pub fn text(text: impl Into<String>) -> Self {
+        ToolResponseContent::Text {
+            text: text.into(),
+            annotations: None,
+        }
+    }
+
+    /// `data` must already be base64 encoded
+    pub fn image(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        ToolResponseContent::Image {
+            data: data.into(),
+            mime_type: mime_type.into(),
+            annotations: None,
+        }
+    }
+
+    /// `data` must already be base64 encoded
+    pub fn audio(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        ToolResponseContent::Audio {
+            data: data.into(),
+            mime_type: mime_type.into(),
+            annotations: None,
+        }
+    }
+
+    pub fn resource(resource: ResourceContents) -> Self {
+        ToolResponseContent::Resource {
+            resource,
+            annotations: None,
+        }
+    }
+
+    pub fn resource_link(resource: Resource) -> Self {
+        ToolResponseContent::ResourceLink(resource)
+    }
+
+    pub fn with_annotations(mut self, annotations: Annotations) -> Self {
+        *self.annotations_mut() = Some(annotations);
+        self
+    }
+
+    pub fn annotations(&self) -> Option<&Annotations> {
+        match self {
+            ToolResponseContent::Text { annotations, .. }
+            | ToolResponseContent::Image { annotations, .. }
+            | ToolResponseContent::Audio { annotations, .. }
+            | ToolResponseContent::Resource { annotations, .. }
+            | ToolResponseContent::ResourceLink(Resource { annotations, .. }) => {
+                annotations.as_ref()
+            }
+        }
+    }
+
+    fn annotations_mut(&mut self) -> &mut Option<Annotations> {
+        match self {
+            ToolResponseContent::Text { annotations, .. }
+            | ToolResponseContent::Image { annotations, .. }
+            | ToolResponseContent::Audio { annotations, .. }
+            | ToolResponseContent::Resource { annotations, .. }
+            | ToolResponseContent::ResourceLink(Resource { annotations, .. }) => annotations,
+        }
+    }
+}
+
+/// Hints for the client about how to use a piece of content
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotations {
+    /// Who the content is intended for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audience: Option<Vec<Role>>,
+    /// Importance from 0 (optional) to 1 (required)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<f64>,
+    /// ISO 8601 timestamp of the last modification
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// Contents of a resource, either text or base64 encoded binary data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResourceContents {
+    Text(TextResourceContents),
+    Blob(BlobResourceContents),
+}
+
+impl ResourceContents {
+    pub fn text(uri: Url, text: impl Into<String>) -> Self {
+        ResourceContents::Text(TextResourceContents {
+            uri,
+            mime_type: None,
+            text: text.into(),
+        })
+    }
+
+    /// `blob` must already be base64 encoded
+    pub fn blob(uri: Url, blob: impl Into<String>) -> Self {
+        ResourceContents::Blob(BlobResourceContents {
+            uri,
+            mime_type: None,
+            blob: blob.into(),
+        })
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        *self.mime_type_mut() = Some(mime_type.into());
+        self
+    }
+
+    pub fn uri(&self) -> &Url {
+        match self {
+            ResourceContents::Text(contents) => &contents.uri,
+            ResourceContents::Blob(contents) => &contents.uri,
+        }
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text(contents) => contents.mime_type.as_deref(),
+            ResourceContents::Blob(contents) => contents.mime_type.as_deref(),
+        }
+    }
+
+    pub fn mime_type_mut(&mut self) -> &mut Option<String> {
+        match self {
+            ResourceContents::Text(contents) => &mut contents.mime_type,
+            ResourceContents::Blob(contents) => &mut contents.mime_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ResourceContents {
+pub struct TextResourceContents {
     pub uri: Url,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobResourceContents {
+    pub uri: Url,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Base64 encoded data
+    pub blob: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -143,19 +590,19 @@ pub struct ToolsListResponse {
     #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
     pub meta: Option<serde_json::Value>,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptsListResponse {
-    pub prompts: Vec<Prompt>,
+    pub prompts: Vec<PromptDefinition>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next_cursor: Option<String>,
     #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
     pub meta: Option<HashMap<String, serde_json::Value>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Prompt {
+pub struct PromptDefinition {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
@@ -163,7 +610,7 @@ pub struct Prompt {
     pub arguments: Option<Vec<PromptArgument>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptArgument {
     pub name: String,
@@ -173,7 +620,60 @@ pub struct PromptArgument {
     pub required: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPromptRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<HashMap<String, String>>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPromptResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptMessage {
+    pub role: Role,
+    pub content: PromptMessageContent,
+}
+
+impl PromptMessage {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: PromptMessageContent::text(text),
+        }
+    }
+
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: PromptMessageContent::text(text),
+        }
+    }
+}
+
+/// Prompt messages carry the same content blocks as tool results
+pub type PromptMessageContent = ToolResponseContent;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourcesListResponse {
     pub resources: Vec<Resource>,
@@ -183,7 +683,7 @@ pub struct ResourcesListResponse {
     pub meta: Option<HashMap<String, serde_json::Value>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Resource {
     pub uri: Url,
@@ -192,6 +692,148 @@ pub struct Resource {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplate {
+    /// RFC 6570 template for the uris of the resources
+    pub uri_template: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplatesListResponse {
+    pub resource_templates: Vec<ResourceTemplate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadResourceRequest {
+    pub uri: Url,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadResourceResponse {
+    pub contents: Vec<ResourceContents>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribeRequest {
+    pub uri: Url,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsubscribeRequest {
+    pub uri: Url,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceUpdatedNotification {
+    pub uri: Url,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteRequest {
+    #[serde(rename = "ref")]
+    pub reference: CompletionReference,
+    pub argument: CompletionArgument,
+    /// Values of arguments the user already filled in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<CompletionContext>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// What is being completed, an argument of a prompt or a variable of a resource template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CompletionReference {
+    #[serde(rename = "ref/prompt")]
+    Prompt { name: String },
+    #[serde(rename = "ref/resource")]
+    Resource { uri: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionArgument {
+    pub name: String,
+    /// What the user typed so far
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteResponse {
+    pub completion: Completion,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    /// At most 100 values
+    pub values: Vec<String>,
+    /// Number of matches, which may exceed the values sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_more: Option<bool>,
+}
+
+/// Syslog severities, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetLevelRequest {
+    pub level: LoggingLevel,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -200,6 +842,9 @@ pub enum ErrorCode {
     ConnectionClosed = -1,
     RequestTimeout = -2,
 
+    // MCP error codes
+    ResourceNotFound = -32002,
+
     // Standard JSON-RPC error codes
     ParseError = -32700,
     InvalidRequest = -32600,
@@ -208,6 +853,31 @@ pub enum ErrorCode {
     InternalError = -32603,
 }
 
+/// An error with a JSON-RPC error code
+/// return it from a request handler to control the code of the error response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ProtocolError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -218,4 +888,226 @@ mod tests {
         let json = serde_json::to_string(&capabilities).unwrap();
         assert_eq!(json, "{}");
     }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let supported = SUPPORTED_PROTOCOL_VERSIONS;
+        assert_eq!(
+            ProtocolVersion::negotiate("2024-11-05", supported),
+            Some(ProtocolVersion::V2024_11_05)
+        );
+        assert_eq!(
+            ProtocolVersion::negotiate("2025-06-18", supported),
+            Some(ProtocolVersion::V2025_06_18)
+        );
+        assert_eq!(
+            ProtocolVersion::negotiate("2023-01-01", supported),
+            Some(ProtocolVersion::LATEST)
+        );
+        assert_eq!(
+            ProtocolVersion::negotiate("2025-06-18", &[ProtocolVersion::V2024_11_05]),
+            Some(ProtocolVersion::V2024_11_05)
+        );
+        assert_eq!(ProtocolVersion::negotiate("2025-06-18", &[]), None);
+        assert!(ProtocolVersion::V2025_03_26 > ProtocolVersion::V2024_11_05);
+    }
+
+    #[test]
+    fn test_server_capability_for_method() {
+        let capabilities = ServerCapabilities {
+            tools: Some(ToolsCapability::default()),
+            resources: Some(ResourceCapabilities::default()),
+            ..Default::default()
+        };
+        let supports = |method| {
+            ServerCapability::required_for(method)
+                .map(|capability| capabilities.supports(capability))
+                .unwrap_or(true)
+        };
+        assert!(supports("tools/call"));
+        assert!(supports("resources/read"));
+        assert!(supports("ping"));
+        assert!(!supports("prompts/list"));
+        assert!(!supports("resources/subscribe"));
+        assert!(!supports("completion/complete"));
+    }
+
+    #[test]
+    fn test_capabilities_round_trip() {
+        let json = serde_json::json!({
+            "tools": {"listChanged": true},
+            "logging": {},
+            "resources": {"subscribe": true},
+            "experimental": {"custom": {"level": 3}, "other": [1, 2]}
+        });
+        let capabilities: ServerCapabilities = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(
+            capabilities.tools.as_ref().unwrap().list_changed,
+            Some(true)
+        );
+        assert!(capabilities.logging.is_some());
+        assert!(capabilities.supports(ServerCapability::ResourceSubscriptions));
+        assert_eq!(
+            capabilities.experimental_capability::<HashMap<String, u32>>("custom"),
+            Some(HashMap::from([("level".to_string(), 3)]))
+        );
+        assert_eq!(serde_json::to_value(&capabilities).unwrap(), json);
+
+        let capabilities = ClientCapabilities::default()
+            .with_experimental("custom", true)
+            .unwrap();
+        assert_eq!(
+            capabilities.experimental_capability::<bool>("custom"),
+            Some(true)
+        );
+        assert_eq!(
+            serde_json::to_value(&capabilities).unwrap(),
+            serde_json::json!({"experimental": {"custom": true}})
+        );
+        // maps with non-string keys cannot be represented in json
+        let invalid = HashMap::from([((1, 2), true)]);
+        assert!(ClientCapabilities::default()
+            .with_experimental("custom", invalid)
+            .is_err());
+    }
+
+    #[test]
+    fn test_tool_hints() {
+        let tool = |annotations: Option<ToolAnnotations>| ToolDefinition {
+            name: "tool".to_string(),
+            title: None,
+            description: None,
+            input_schema: serde_json::json!({"type": "object"}),
+            output_schema: None,
+            annotations,
+        };
+        let unannotated = tool(None);
+        assert!(!unannotated.is_read_only());
+        assert!(unannotated.is_destructive());
+        assert!(!unannotated.is_idempotent());
+        assert!(unannotated.is_open_world());
+
+        // destructive and idempotent hints do not apply to read only tools
+        let read_only = tool(Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            ..Default::default()
+        }));
+        assert!(read_only.is_read_only());
+        assert!(!read_only.is_destructive());
+        assert!(!read_only.is_idempotent());
+    }
+
+    #[test]
+    fn test_prompt_message_serialization() {
+        let message = PromptMessage {
+            role: Role::User,
+            content: PromptMessageContent::audio("aGVsbG8=", "audio/wav").with_annotations(
+                Annotations {
+                    audience: Some(vec![Role::User]),
+                    priority: None,
+                    last_modified: None,
+                },
+            ),
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({
+                "role": "user",
+                "content": {
+                    "type": "audio",
+                    "data": "aGVsbG8=",
+                    "mimeType": "audio/wav",
+                    "annotations": {"audience": ["user"]}
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_resource_contents_serialization() {
+        let uri = Url::parse("file:///notes.txt").unwrap();
+        let text = ResourceContents::text(uri.clone(), "hello").with_mime_type("text/plain");
+        let json = serde_json::json!({"uri": "file:///notes.txt", "mimeType": "text/plain", "text": "hello"});
+        assert_eq!(serde_json::to_value(&text).unwrap(), json);
+        assert!(matches!(
+            serde_json::from_value(json).unwrap(),
+            ResourceContents::Text(_)
+        ));
+
+        let json = serde_json::json!({"uri": "file:///notes.txt", "blob": "aGVsbG8="});
+        let blob: ResourceContents = serde_json::from_value(json.clone()).unwrap();
+        assert!(matches!(blob, ResourceContents::Blob(_)));
+        assert_eq!(blob.uri(), &uri);
+        assert_eq!(blob.mime_type(), None);
+        assert_eq!(serde_json::to_value(&blob).unwrap(), json);
+    }
+
+    #[test]
+    fn test_tool_response_content_round_trip() {
+        let annotations = Annotations {
+            audience: Some(vec![Role::User]),
+            priority: Some(0.5),
+            last_modified: None,
+        };
+        let uri = Url::parse("file:///notes.txt").unwrap();
+        let cases = [
+            (
+                ToolResponseContent::text("hello").with_annotations(annotations.clone()),
+                serde_json::json!({
+                    "type": "text",
+                    "text": "hello",
+                    "annotations": {"audience": ["user"], "priority": 0.5}
+                }),
+            ),
+            (
+                ToolResponseContent::image("aGVsbG8=", "image/png"),
+                serde_json::json!({"type": "image", "data": "aGVsbG8=", "mimeType": "image/png"}),
+            ),
+            (
+                ToolResponseContent::audio("aGVsbG8=", "audio/wav"),
+                serde_json::json!({"type": "audio", "data": "aGVsbG8=", "mimeType": "audio/wav"}),
+            ),
+            (
+                ToolResponseContent::resource(ResourceContents::text(uri.clone(), "hello")),
+                serde_json::json!({
+                    "type": "resource",
+                    "resource": {"uri": "file:///notes.txt", "text": "hello"}
+                }),
+            ),
+            (
+                ToolResponseContent::resource(ResourceContents::blob(uri.clone(), "aGVsbG8="))
+                    .with_annotations(annotations.clone()),
+                serde_json::json!({
+                    "type": "resource",
+                    "resource": {"uri": "file:///notes.txt", "blob": "aGVsbG8="},
+                    "annotations": {"audience": ["user"], "priority": 0.5}
+                }),
+            ),
+            (
+                ToolResponseContent::resource_link(Resource {
+                    uri,
+                    name: "notes".to_string(),
+                    description: None,
+                    mime_type: Some("text/plain".to_string()),
+                    annotations: Some(annotations.clone()),
+                }),
+                serde_json::json!({
+                    "type": "resource_link",
+                    "uri": "file:///notes.txt",
+                    "name": "notes",
+                    "mimeType": "text/plain",
+                    "annotations": {"audience": ["user"], "priority": 0.5}
+                }),
+            ),
+        ];
+        for (content, json) in cases {
+            assert_eq!(serde_json::to_value(&content).unwrap(), json);
+            assert_eq!(
+                serde_json::from_value::<ToolResponseContent>(json).unwrap(),
+                content
+            );
+        }
+    }
 }